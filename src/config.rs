@@ -1,57 +1,120 @@
+use crate::services::http::config::{HttpConfig, PartialHttpConfig};
+use crate::services::iperf::config::{IperfConfig, PartialIperfConfig};
 use crate::services::ping::config::{PartialPingConfig, PingConfig};
 use crate::services::speedtest::config::{PartialSpeedtestConfig, SpeedtestConfig};
+use crate::services::throughput::config::{PartialThroughputConfig, ThroughputConfig};
+use crate::sinks::influxdb::config::{InfluxDBConfig, PartialInfluxDBConfig};
+use crate::sinks::prometheus::config::{PartialPrometheusConfig, PrometheusConfig};
 use clap::{Parser, ValueEnum};
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
 use url::Url;
 
-#[derive(Debug, Clone, Parser)]
+#[derive(Debug, Clone, Default, Parser, Deserialize)]
 #[command(author, version, about, long_about = None)]
+#[serde(deny_unknown_fields)]
 pub struct PartialConfig {
-    #[arg(long, env = "INFLUXDB_URL", value_parser = parse_http_url)]
-    pub influxdb_url: Url,
-    #[arg(long, env = "INFLUXDB_TOKEN")]
-    pub influxdb_token: String,
-    #[arg(long, env = "INFLUXDB_ORG", default_value = "org")]
-    pub influxdb_org: String,
-    #[arg(long, env = "INFLUXDB_BUCKET", default_value = "speedtest")]
-    pub influxdb_bucket: String,
-    #[arg(value_enum, long, env = "LOG_LEVEL", default_value_t = LogLevel::Info)]
-    pub log_level: LogLevel,
+    #[arg(long, env = "CONFIG_FILE", help = "TOML or YAML configuration file")]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+    #[arg(value_enum, long, env = "LOG_LEVEL", help = "[default: info]")]
+    pub log_level: Option<LogLevel>,
+    #[command(flatten)]
+    #[serde(default)]
+    pub influxdb: PartialInfluxDBConfig,
     #[command(flatten)]
+    #[serde(default)]
+    pub prometheus: PartialPrometheusConfig,
+    #[command(flatten)]
+    #[serde(default)]
     pub speedtest: PartialSpeedtestConfig,
     #[command(flatten)]
+    #[serde(default)]
     pub ping: PartialPingConfig,
+    #[command(flatten)]
+    #[serde(default)]
+    pub iperf: PartialIperfConfig,
+    #[command(flatten)]
+    #[serde(default)]
+    pub throughput: PartialThroughputConfig,
+    #[command(flatten)]
+    #[serde(default)]
+    pub http: PartialHttpConfig,
+}
+
+impl PartialConfig {
+    /// Fills values not given by flags or environment variables from the config file.
+    pub fn with_file(self) -> Result<Self, String> {
+        let Some(path) = &self.config else {
+            return Ok(self);
+        };
+
+        let file = read_config_file(path)?;
+        Ok(self.merge(file))
+    }
+
+    /// Values of `self` take precedence over values of `other`.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            config: self.config.or(other.config),
+            log_level: self.log_level.or(other.log_level),
+            influxdb: self.influxdb.merge(other.influxdb),
+            prometheus: self.prometheus.merge(other.prometheus),
+            speedtest: self.speedtest.merge(other.speedtest),
+            ping: self.ping.merge(other.ping),
+            iperf: self.iperf.merge(other.iperf),
+            throughput: self.throughput.merge(other.throughput),
+            http: self.http.merge(other.http),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub influxdb_url: Url,
-    pub influxdb_token: String,
-    pub influxdb_org: String,
-    pub influxdb_bucket: String,
     pub log_level: LogLevel,
-    pub speedtest: Option<SpeedtestConfig>,
-    pub ping: Option<PingConfig>,
+    pub influxdb: Option<InfluxDBConfig>,
+    pub prometheus: Option<PrometheusConfig>,
+    pub speedtest: BTreeMap<String, SpeedtestConfig>,
+    pub ping: BTreeMap<String, PingConfig>,
+    pub iperf: BTreeMap<String, IperfConfig>,
+    pub throughput: BTreeMap<String, ThroughputConfig>,
+    pub http: BTreeMap<String, HttpConfig>,
 }
 
 impl TryFrom<PartialConfig> for Config {
     type Error = String;
     fn try_from(config: PartialConfig) -> Result<Self, Self::Error> {
+        let influxdb: Option<InfluxDBConfig> = config.influxdb.try_into()?;
+        let prometheus: Option<PrometheusConfig> = config.prometheus.try_into()?;
+        if influxdb.is_none() && prometheus.is_none() {
+            return Err("INFLUXDB_URL or PROMETHEUS_LISTEN is required".to_string());
+        }
+
         let speedtest = config.speedtest.try_into()?;
         let ping = config.ping.try_into()?;
+        let iperf = config.iperf.try_into()?;
+        let throughput = config.throughput.try_into()?;
+        let http = config.http.try_into()?;
 
         Ok(Config {
-            influxdb_url: config.influxdb_url,
-            influxdb_token: config.influxdb_token,
-            influxdb_org: config.influxdb_org,
-            influxdb_bucket: config.influxdb_bucket,
-            log_level: config.log_level,
+            log_level: config.log_level.unwrap_or(LogLevel::Info),
+            influxdb,
+            prometheus,
             speedtest,
             ping,
+            iperf,
+            throughput,
+            http,
         })
     }
 }
 
-#[derive(Debug, Clone, Copy, ValueEnum)]
+#[derive(Debug, Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     Error,
     Warn,
@@ -70,7 +133,7 @@ impl From<LogLevel> for log::Level {
     }
 }
 
-fn parse_http_url(text: &str) -> Result<Url, String> {
+pub fn parse_http_url(text: &str) -> Result<Url, String> {
     let url = Url::parse(text).map_err(|e| e.to_string())?;
 
     match url.scheme() {
@@ -78,3 +141,122 @@ fn parse_http_url(text: &str) -> Result<Url, String> {
         _ => Err("URL scheme must be http or https".to_string()),
     }
 }
+
+pub fn deserialize_http_url<'de, D>(deserializer: D) -> Result<Option<Url>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = String::deserialize(deserializer)?;
+    parse_http_url(&text)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Top level parameters of a service define the instance named `default_name`,
+/// other `instances` come from the config file. `kind` names the service in errors.
+pub fn expand_instances<P, C>(
+    default_name: &str,
+    top: P,
+    instances: BTreeMap<String, P>,
+    kind: &str,
+) -> Result<BTreeMap<String, C>, String>
+where
+    P: TryInto<Option<C>, Error = String>,
+{
+    let mut configs = BTreeMap::new();
+    if let Some(config) = top.try_into()? {
+        configs.insert(default_name.to_string(), config);
+    }
+
+    for (name, instance) in instances {
+        let config = instance
+            .try_into()
+            .map_err(|e| format!("{kind} instance \"{name}\": {e}"))?
+            .ok_or(format!("{kind} instance \"{name}\" is empty"))?;
+        if configs.insert(name.clone(), config).is_some() {
+            return Err(format!("{kind} instance \"{name}\" is defined twice"));
+        }
+    }
+
+    Ok(configs)
+}
+
+/// Instances are only defined in the config file, so they are taken as a whole from one side.
+pub fn merge_instances<P>(
+    instances: BTreeMap<String, P>,
+    other: BTreeMap<String, P>,
+) -> BTreeMap<String, P> {
+    if instances.is_empty() {
+        other
+    } else {
+        instances
+    }
+}
+
+/// Accepts both a comma separated string and a list, the result is always comma separated.
+pub fn deserialize_comma_list<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match StringOrList::deserialize(deserializer)? {
+        StringOrList::Text(text) => Ok(Some(text)),
+        StringOrList::List(list) => Ok(Some(list.join(","))),
+    }
+}
+
+/// Value given either as a single string or as a list of strings.
+pub enum StringOrList {
+    Text(String),
+    List(Vec<String>),
+}
+
+impl<'de> Deserialize<'de> for StringOrList {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(StringOrListVisitor)
+    }
+}
+
+struct StringOrListVisitor;
+
+impl<'de> Visitor<'de> for StringOrListVisitor {
+    type Value = StringOrList;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string or a list of strings")
+    }
+
+    fn visit_str<E>(self, text: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(StringOrList::Text(text.to_string()))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut list = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            list.push(item);
+        }
+        Ok(StringOrList::List(list))
+    }
+}
+
+fn read_config_file(path: &Path) -> Result<PartialConfig, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file {}: {}", path.display(), e))?;
+
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let config = match extension {
+        "toml" => toml::from_str(&text).map_err(|e| e.to_string()),
+        "yaml" | "yml" => serde_yaml::from_str(&text).map_err(|e| e.to_string()),
+        _ => Err("Config file must have .toml, .yaml or .yml extension".to_string()),
+    };
+
+    config.map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+}
@@ -1,11 +1,16 @@
 use crate::config::{Config, PartialConfig};
-use crate::influxdb::InfluxDB;
+use crate::services::http::service::HttpService;
+use crate::services::iperf::service::IperfService;
 use crate::services::ping::service::PingService;
 use crate::services::service::Service;
 use crate::services::speedtest::service::SpeedtestService;
+use crate::services::throughput::service::ThroughputService;
+use crate::sinks::influxdb::sink::InfluxDB;
+use crate::sinks::prometheus::sink::Prometheus;
+use crate::sinks::sink::Sinks;
 use clap::Parser;
 use influxdb2::models::Status;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
@@ -15,16 +20,17 @@ use tokio_cron_scheduler::{Job, JobScheduler};
 use dotenv::dotenv;
 
 mod config;
-mod influxdb;
+mod measurement;
 mod services;
+mod sinks;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     #[cfg(debug_assertions)]
     dotenv().ok();
 
-    let config = PartialConfig::parse();
-    let config: Config = match config.try_into() {
+    let config = PartialConfig::parse().with_file();
+    let config: Config = match config.and_then(Config::try_from) {
         Ok(config) => config,
         Err(e) => {
             eprintln!("Failed to validate configuration: {}", e);
@@ -37,27 +43,56 @@ async fn main() -> anyhow::Result<()> {
         return Err(e.into());
     }
 
-    info!("Connecting to InfluxDB: {}", config.influxdb_url);
-    let client = InfluxDB::new(
-        &config.influxdb_url.to_string(),
-        &config.influxdb_org,
-        &config.influxdb_token,
-        &config.influxdb_bucket,
-    );
-    check_health(&client).await?;
+    let mut sinks = Sinks::default();
+    if let Some(influxdb) = &config.influxdb {
+        info!("Connecting to InfluxDB: {}", influxdb.url);
+        let client = InfluxDB::new(
+            influxdb.url.as_ref(),
+            &influxdb.org,
+            &influxdb.token,
+            &influxdb.bucket,
+        )
+        .with_timestamp(influxdb.timestamp);
+        let health = check_health(&client).await;
+        if let Some(buffer) = &influxdb.buffer {
+            if let Err(e) = health {
+                warn!("InfluxDB is unreachable, writes will be buffered: {}", e);
+            }
+            info!(
+                "Buffering failed InfluxDB writes in: {}",
+                buffer.path.display()
+            );
+            sinks.add(client.with_buffer(buffer));
+        } else {
+            health?;
+            sinks.add(client);
+        }
+    }
+    if let Some(prometheus) = &config.prometheus {
+        info!("Starting Prometheus exporter: {}", prometheus.listen);
+        let exporter = Prometheus::new(prometheus.clone());
+        exporter.start()?;
+        sinks.add(exporter);
+    }
 
-    let shared_db = Arc::new(client);
     let shared_config = Arc::new(config);
 
     debug!("Creating job scheduler");
     let mut scheduler = JobScheduler::new().await?;
-    if let Some(job) = create_speedtest(shared_config.clone(), shared_db.clone())? {
+    for job in create_speedtests(shared_config.clone(), sinks.clone())? {
+        scheduler.add(job).await?;
+    }
+    for job in create_pings(shared_config.clone(), sinks.clone())? {
         scheduler.add(job).await?;
-        info!("Added speedtest service");
     }
-    if let Some(job) = create_ping(shared_config.clone(), shared_db.clone())? {
+    for job in create_iperfs(shared_config.clone(), sinks.clone())? {
+        scheduler.add(job).await?;
+    }
+    for job in create_throughputs(shared_config.clone(), sinks.clone())? {
+        scheduler.add(job).await?;
+    }
+    for job in create_https(shared_config.clone(), sinks.clone())? {
         scheduler.add(job).await?;
-        info!("Added ping service");
     }
 
     scheduler.start().await?;
@@ -75,27 +110,62 @@ async fn main() -> anyhow::Result<()> {
     }
 }
 
-fn create_speedtest(config: Arc<Config>, db: Arc<InfluxDB>) -> anyhow::Result<Option<Job>> {
-    debug!("Creating speedtest service");
-    if let Some(config) = &config.speedtest {
-        let service = SpeedtestService::new(db, config.clone(), "speedtest");
-        let job = create_service_job(&config.cron, service)?;
-        return Ok(Some(job));
+fn create_speedtests(config: Arc<Config>, sinks: Sinks) -> anyhow::Result<Vec<Job>> {
+    let mut jobs = Vec::new();
+    for (name, config) in &config.speedtest {
+        debug!("Creating speedtest service: {}", name);
+        let service = SpeedtestService::new(config.clone(), name);
+        jobs.push(create_service_job(&config.cron, service, sinks.clone())?);
+        info!("Added speedtest service: {}", name);
+    }
+    Ok(jobs)
+}
+
+fn create_pings(config: Arc<Config>, sinks: Sinks) -> anyhow::Result<Vec<Job>> {
+    let mut jobs = Vec::new();
+    for (name, config) in &config.ping {
+        debug!("Creating ping service: {}", name);
+        let service = PingService::new(config.clone(), name);
+        jobs.push(create_service_job(&config.cron, service, sinks.clone())?);
+        info!("Added ping service: {}", name);
     }
-    Ok(None)
+    Ok(jobs)
 }
 
-fn create_ping(config: Arc<Config>, db: Arc<InfluxDB>) -> anyhow::Result<Option<Job>> {
-    debug!("Creating ping service");
-    if let Some(config) = &config.ping {
-        let service = PingService::new(db, config.clone(), "ping");
-        let job = create_service_job(&config.cron, service)?;
-        return Ok(Some(job));
+fn create_iperfs(config: Arc<Config>, sinks: Sinks) -> anyhow::Result<Vec<Job>> {
+    let mut jobs = Vec::new();
+    for (name, config) in &config.iperf {
+        debug!("Creating iperf service: {}", name);
+        let service = IperfService::new(config.clone(), name);
+        jobs.push(create_service_job(&config.cron, service, sinks.clone())?);
+        info!("Added iperf service: {}", name);
     }
-    Ok(None)
+    Ok(jobs)
 }
 
-fn create_service_job<S>(cron: &str, service: S) -> anyhow::Result<Job>
+fn create_throughputs(config: Arc<Config>, sinks: Sinks) -> anyhow::Result<Vec<Job>> {
+    let mut jobs = Vec::new();
+    for (name, config) in &config.throughput {
+        debug!("Creating throughput service: {}", name);
+        let service = ThroughputService::new(config.clone(), name)?;
+        jobs.push(create_service_job(&config.cron, service, sinks.clone())?);
+        info!("Added throughput service: {}", name);
+    }
+    Ok(jobs)
+}
+
+fn create_https(config: Arc<Config>, sinks: Sinks) -> anyhow::Result<Vec<Job>> {
+    let mut jobs = Vec::new();
+    for (name, config) in &config.http {
+        debug!("Creating http service: {}", name);
+        let service = HttpService::new(config.clone(), name)?;
+        jobs.push(create_service_job(&config.cron, service, sinks.clone())?);
+        info!("Added http service: {}", name);
+    }
+    Ok(jobs)
+}
+
+fn create_service_job<S>(cron: &str, service: S, sinks: Sinks) -> anyhow::Result<Job>
 where
     S: Service + Send + Sync + 'static,
 {
@@ -107,9 +177,16 @@ where
     let service = Arc::new(service);
     let job = Job::new_async(cron, move |_, _| {
         let service = service.clone();
+        let sinks = sinks.clone();
         Box::pin(async move {
             info!("Executing service \"{}\"", service.name());
-            let result = service.execute().await;
+            let result = match service.execute().await {
+                Ok(measurements) => {
+                    debug!("Writing data of service \"{}\" to sinks", service.name());
+                    sinks.write(&measurements).await
+                }
+                Err(e) => Err(e),
+            };
             match result {
                 Ok(_) => {
                     info!("Service \"{}\" completed successfully", service.name());
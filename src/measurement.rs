@@ -0,0 +1,93 @@
+use chrono::{DateTime, Utc};
+use influxdb2::models::FieldValue;
+use std::collections::BTreeMap;
+
+/// Single measurement produced by a service, independent of the sink it ends up in.
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub name: String,
+    pub tags: BTreeMap<String, String>,
+    pub fields: BTreeMap<String, FieldValue>,
+    /// Time the measurement was taken at, sinks use the time of writing when missing
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+impl Measurement {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            tags: BTreeMap::new(),
+            fields: BTreeMap::new(),
+            timestamp: None,
+        }
+    }
+
+    pub fn tag(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn field(mut self, name: impl Into<String>, value: impl Into<FieldValue>) -> Self {
+        self.fields.insert(name.into(), value.into());
+        self
+    }
+
+    /// Failed measurement is tagged with `status=error` and the category of the error,
+    /// the numeric `success` field lets the failures be counted in every sink.
+    pub fn failure(self, category: ErrorCategory, message: impl Into<String>) -> Self {
+        self.tag("status", "error")
+            .tag("error_category", category.as_str())
+            .field("error", message.into())
+            .field("success", 0i64)
+    }
+
+    pub fn timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+}
+
+/// Reason of a failed measurement, written as the `error_category` tag.
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorCategory {
+    /// Command could not be started, e.g. it is not installed
+    Command,
+    /// Output of the command could not be parsed
+    Parse,
+    Timeout,
+    Dns,
+    /// Server is not reachable or refuses the connection
+    Connect,
+    Tls,
+    /// Request could not be sent or the response could not be read
+    Request,
+    /// Server responded with an error status
+    Status,
+    NoServer,
+    License,
+    /// Server is running a test of another client
+    Busy,
+    /// ICMP socket could not be opened or used
+    Socket,
+    Unknown,
+}
+
+impl ErrorCategory {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCategory::Command => "command",
+            ErrorCategory::Parse => "parse",
+            ErrorCategory::Timeout => "timeout",
+            ErrorCategory::Dns => "dns",
+            ErrorCategory::Connect => "connect",
+            ErrorCategory::Tls => "tls",
+            ErrorCategory::Request => "request",
+            ErrorCategory::Status => "status",
+            ErrorCategory::NoServer => "no_server",
+            ErrorCategory::License => "license",
+            ErrorCategory::Busy => "busy",
+            ErrorCategory::Socket => "socket",
+            ErrorCategory::Unknown => "unknown",
+        }
+    }
+}
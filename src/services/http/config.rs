@@ -0,0 +1,92 @@
+use crate::config::{deserialize_comma_list, expand_instances, merge_instances, parse_http_url};
+use clap::Args;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use url::Url;
+
+#[derive(Debug, Clone, Default, Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartialHttpConfig {
+    #[arg(name = "HTTP_CRON", long = "http-cron", env = "HTTP_CRON")]
+    pub cron: Option<String>,
+    #[arg(
+        name = "HTTP_URLS",
+        long = "http-urls",
+        env = "HTTP_URLS",
+        help = "Comma separated list of http or https URLs"
+    )]
+    #[serde(default, deserialize_with = "deserialize_comma_list")]
+    pub urls: Option<String>,
+    #[arg(
+        name = "HTTP_TIMEOUT",
+        long = "http-timeout",
+        env = "HTTP_TIMEOUT",
+        help = "Time limit of every request [seconds] [default: 10]"
+    )]
+    pub timeout: Option<u64>,
+    #[arg(skip)]
+    #[serde(default)]
+    pub instances: BTreeMap<String, PartialHttpConfig>,
+}
+
+impl PartialHttpConfig {
+    /// Values of `self` take precedence over values of `other`.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            cron: self.cron.or(other.cron),
+            urls: self.urls.or(other.urls),
+            timeout: self.timeout.or(other.timeout),
+            instances: merge_instances(self.instances, other.instances),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    pub cron: String,
+    pub urls: Vec<Url>,
+    pub timeout: Duration,
+}
+
+impl TryFrom<PartialHttpConfig> for BTreeMap<String, HttpConfig> {
+    type Error = String;
+    fn try_from(mut config: PartialHttpConfig) -> Result<Self, Self::Error> {
+        let instances = std::mem::take(&mut config.instances);
+        expand_instances("http", config, instances, "Http")
+    }
+}
+
+impl TryFrom<PartialHttpConfig> for Option<HttpConfig> {
+    type Error = String;
+    fn try_from(config: PartialHttpConfig) -> Result<Self, Self::Error> {
+        if !config.instances.is_empty() {
+            return Err("Instances can not be nested".to_string());
+        }
+        if config.cron.is_none() && config.urls.is_none() {
+            return Ok(None);
+        }
+
+        let cron = config
+            .cron
+            .ok_or("HTTP_CRON is required for \"HTTP_\" parameters")?;
+        let urls = config
+            .urls
+            .ok_or("HTTP_URLS is required for \"HTTP_\" parameters")?
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(|url| parse_http_url(url).map_err(|e| format!("Invalid HTTP URL \"{url}\": {e}")))
+            .collect::<Result<Vec<_>, _>>()?;
+        let timeout = config.timeout.unwrap_or(10);
+        if timeout == 0 {
+            return Err("HTTP_TIMEOUT must be greater than 0".to_string());
+        }
+
+        Ok(Some(HttpConfig {
+            cron,
+            urls,
+            timeout: Duration::from_secs(timeout),
+        }))
+    }
+}
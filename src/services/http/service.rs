@@ -0,0 +1,218 @@
+use crate::measurement::{ErrorCategory, Measurement};
+use crate::services::http::config::HttpConfig;
+use crate::services::service::Service;
+use async_trait::async_trait;
+use chrono::Utc;
+use hyper::body::HttpBody;
+use hyper::header::{HOST, USER_AGENT};
+use hyper::{Body, Request};
+use log::{debug, warn};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{lookup_host, TcpStream};
+use tokio::time::{timeout, Instant};
+use tokio_native_tls::native_tls;
+use tokio_native_tls::TlsConnector;
+use url::{Host, Url};
+
+/// Durations of the phases of a single request, filled in as the request progresses.
+#[derive(Default)]
+struct Phases {
+    dns: Option<Duration>,
+    connect: Option<Duration>,
+    tls: Option<Duration>,
+    ttfb: Option<Duration>,
+    status: Option<u16>,
+    size: Option<u64>,
+}
+
+impl Phases {
+    /// Failure is attributed to the first phase that did not complete.
+    fn failed(&self, url: &Url) -> ErrorCategory {
+        if self.connect.is_none() {
+            match url.host() {
+                Some(Host::Domain(_)) if self.dns.is_none() => ErrorCategory::Dns,
+                _ => ErrorCategory::Connect,
+            }
+        } else if url.scheme() == "https" && self.tls.is_none() {
+            ErrorCategory::Tls
+        } else {
+            ErrorCategory::Request
+        }
+    }
+}
+
+pub struct HttpService {
+    config: HttpConfig,
+    name: String,
+    tls: TlsConnector,
+}
+
+impl HttpService {
+    pub fn new(config: HttpConfig, name: &str) -> anyhow::Result<Self> {
+        let tls = native_tls::TlsConnector::new()?;
+        Ok(Self {
+            config,
+            name: name.to_string(),
+            tls: tls.into(),
+        })
+    }
+
+    async fn measure(&self, url: &Url) -> Measurement {
+        debug!(target: &self.name, "Probing URL: {}", url);
+        let measurement = Measurement::new(&self.name)
+            .tag("url", url.as_str())
+            .timestamp(Utc::now());
+
+        let started = Instant::now();
+        let mut phases = Phases::default();
+        let result = match timeout(self.config.timeout, self.probe(url, &mut phases)).await {
+            Ok(result) => result.map_err(|e| (phases.failed(url), e.to_string())),
+            Err(_) => Err((
+                ErrorCategory::Timeout,
+                format!("Timed out after {:?}", self.config.timeout),
+            )),
+        };
+        let total = started.elapsed();
+
+        //  Phases completed before a failure are kept, they show where the time went
+        let mut measurement = [
+            ("dns", phases.dns),
+            ("connect", phases.connect),
+            ("tls", phases.tls),
+            ("ttfb", phases.ttfb),
+        ]
+        .into_iter()
+        .filter_map(|(name, duration)| Some((name, duration?)))
+        .fold(measurement, |measurement, (name, duration)| {
+            measurement.field(name, duration.as_secs_f64() * 1000.0)
+        });
+        if let Some(status) = phases.status {
+            measurement = measurement.field("status_code", status as i64);
+        }
+
+        match result {
+            Ok(()) => {
+                measurement = measurement
+                    .field("success", 1i64)
+                    .field("total", total.as_secs_f64() * 1000.0);
+                if let Some(size) = phases.size {
+                    measurement = measurement.field("size", size as i64);
+                }
+                measurement
+            }
+            Err((category, message)) => {
+                warn!(target: &self.name, "Failed to probe {} ({}): {}", url, category.as_str(), message);
+                measurement.failure(category, message)
+            }
+        }
+    }
+
+    async fn probe(&self, url: &Url, phases: &mut Phases) -> anyhow::Result<()> {
+        let host = url
+            .host()
+            .ok_or(anyhow::anyhow!("URL has no host"))?
+            .to_owned();
+        let port = url
+            .port_or_known_default()
+            .ok_or(anyhow::anyhow!("URL has no port"))?;
+
+        //  Addresses are resolved here so the lookup can be timed on its own
+        let addrs: Vec<SocketAddr> = match &host {
+            Host::Domain(domain) => {
+                let started = Instant::now();
+                let addrs = lookup_host((domain.as_str(), port)).await?.collect();
+                phases.dns = Some(started.elapsed());
+                addrs
+            }
+            Host::Ipv4(ip) => vec![SocketAddr::new((*ip).into(), port)],
+            Host::Ipv6(ip) => vec![SocketAddr::new((*ip).into(), port)],
+        };
+
+        let started = Instant::now();
+        let stream = connect(&addrs).await?;
+        phases.connect = Some(started.elapsed());
+
+        if url.scheme() == "https" {
+            let domain = match &host {
+                Host::Domain(domain) => domain.clone(),
+                Host::Ipv4(ip) => ip.to_string(),
+                Host::Ipv6(ip) => ip.to_string(),
+            };
+            let started = Instant::now();
+            let stream = self.tls.connect(&domain, stream).await?;
+            phases.tls = Some(started.elapsed());
+            request(stream, url, phases).await
+        } else {
+            request(stream, url, phases).await
+        }
+    }
+}
+
+/// Connects to the first address that accepts the connection.
+async fn connect(addrs: &[SocketAddr]) -> anyhow::Result<TcpStream> {
+    let mut error = anyhow::anyhow!("Host has no addresses");
+    for addr in addrs {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => error = anyhow::anyhow!("Failed to connect to {}: {}", addr, e),
+        }
+    }
+    Err(error)
+}
+
+/// Sends a GET request over `stream` and reads the whole response body, redirects are not followed.
+async fn request<S>(stream: S, url: &Url, phases: &mut Phases) -> anyhow::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await?;
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let path = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+    let request = Request::get(path)
+        .header(HOST, host)
+        .header(
+            USER_AGENT,
+            concat!("speedflux-rs/", env!("CARGO_PKG_VERSION")),
+        )
+        .body(Body::empty())?;
+
+    let started = Instant::now();
+    let response = sender.send_request(request).await?;
+    phases.ttfb = Some(started.elapsed());
+    phases.status = Some(response.status().as_u16());
+
+    let mut body = response.into_body();
+    let mut size = 0;
+    while let Some(chunk) = body.data().await {
+        size += chunk?.len() as u64;
+    }
+    phases.size = Some(size);
+    Ok(())
+}
+
+#[async_trait]
+impl Service for HttpService {
+    fn name(&self) -> String {
+        format!("http/{}", self.name)
+    }
+
+    async fn execute(&self) -> anyhow::Result<Vec<Measurement>> {
+        let mut measurements = Vec::new();
+        for url in &self.config.urls {
+            measurements.push(self.measure(url).await);
+        }
+        Ok(measurements)
+    }
+}
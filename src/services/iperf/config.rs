@@ -0,0 +1,189 @@
+use crate::config::{deserialize_comma_list, expand_instances, merge_instances};
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartialIperfConfig {
+    #[arg(name = "IPERF_CRON", long = "iperf-cron", env = "IPERF_CRON")]
+    pub cron: Option<String>,
+    #[arg(
+        name = "IPERF_SERVERS",
+        long = "iperf-servers",
+        env = "IPERF_SERVERS",
+        help = "Comma separated list of `host` or `host:port`"
+    )]
+    #[serde(default, deserialize_with = "deserialize_comma_list")]
+    pub servers: Option<String>,
+    #[arg(
+        value_enum,
+        name = "IPERF_PROTOCOL",
+        long = "iperf-protocol",
+        env = "IPERF_PROTOCOL",
+        help = "[default: tcp]"
+    )]
+    pub protocol: Option<IperfProtocol>,
+    #[arg(
+        name = "IPERF_REVERSE",
+        long = "iperf-reverse",
+        env = "IPERF_REVERSE",
+        help = "Server sends and client receives [default: false]"
+    )]
+    pub reverse: Option<bool>,
+    #[arg(
+        name = "IPERF_PARALLEL",
+        long = "iperf-parallel",
+        env = "IPERF_PARALLEL",
+        help = "Number of parallel streams [default: 1]"
+    )]
+    pub parallel: Option<u32>,
+    #[arg(
+        name = "IPERF_DURATION",
+        long = "iperf-duration",
+        env = "IPERF_DURATION",
+        help = "[seconds] [default: 10]"
+    )]
+    pub duration: Option<u64>,
+    #[arg(
+        name = "IPERF_BITRATE",
+        long = "iperf-bitrate",
+        env = "IPERF_BITRATE",
+        help = "Target bitrate, e.g. `100M`, iperf3 limits UDP to 1 Mbit/s by default"
+    )]
+    pub bitrate: Option<String>,
+    #[arg(skip)]
+    #[serde(default)]
+    pub instances: BTreeMap<String, PartialIperfConfig>,
+}
+
+impl PartialIperfConfig {
+    /// Values of `self` take precedence over values of `other`.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            cron: self.cron.or(other.cron),
+            servers: self.servers.or(other.servers),
+            protocol: self.protocol.or(other.protocol),
+            reverse: self.reverse.or(other.reverse),
+            parallel: self.parallel.or(other.parallel),
+            duration: self.duration.or(other.duration),
+            bitrate: self.bitrate.or(other.bitrate),
+            instances: merge_instances(self.instances, other.instances),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IperfConfig {
+    pub cron: String,
+    pub servers: Vec<IperfServer>,
+    pub protocol: IperfProtocol,
+    pub reverse: bool,
+    pub parallel: u32,
+    pub duration: Duration,
+    pub bitrate: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IperfServer {
+    pub host: String,
+    pub port: Option<u16>,
+    /// Used as the `server` tag, as it was configured
+    pub label: String,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IperfProtocol {
+    Tcp,
+    Udp,
+}
+
+impl IperfProtocol {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IperfProtocol::Tcp => "tcp",
+            IperfProtocol::Udp => "udp",
+        }
+    }
+}
+
+impl TryFrom<PartialIperfConfig> for BTreeMap<String, IperfConfig> {
+    type Error = String;
+    fn try_from(mut config: PartialIperfConfig) -> Result<Self, Self::Error> {
+        let instances = std::mem::take(&mut config.instances);
+        expand_instances("iperf", config, instances, "Iperf")
+    }
+}
+
+impl TryFrom<PartialIperfConfig> for Option<IperfConfig> {
+    type Error = String;
+    fn try_from(config: PartialIperfConfig) -> Result<Self, Self::Error> {
+        if !config.instances.is_empty() {
+            return Err("Instances can not be nested".to_string());
+        }
+        if config.cron.is_none() && config.servers.is_none() {
+            return Ok(None);
+        }
+
+        let cron = config
+            .cron
+            .ok_or("IPERF_CRON is required for \"IPERF_\" parameters")?;
+        let servers = config
+            .servers
+            .ok_or("IPERF_SERVERS is required for \"IPERF_\" parameters")?
+            .split(',')
+            .map(str::trim)
+            .filter(|server| !server.is_empty())
+            .map(parse_server)
+            .collect::<Result<Vec<_>, _>>()?;
+        let protocol = config.protocol.unwrap_or(IperfProtocol::Tcp);
+        let reverse = config.reverse.unwrap_or(false);
+        let parallel = config.parallel.unwrap_or(1);
+        if parallel == 0 {
+            return Err("IPERF_PARALLEL must be greater than 0".to_string());
+        }
+        let duration = config.duration.unwrap_or(10);
+        if duration == 0 {
+            return Err("IPERF_DURATION must be greater than 0".to_string());
+        }
+
+        Ok(Some(IperfConfig {
+            cron,
+            servers,
+            protocol,
+            reverse,
+            parallel,
+            duration: Duration::from_secs(duration),
+            bitrate: config.bitrate,
+        }))
+    }
+}
+
+/// Accepts `host`, `host:port` and `[ipv6]:port`.
+fn parse_server(text: &str) -> Result<IperfServer, String> {
+    let (host, port) = match text.strip_prefix('[') {
+        Some(rest) => {
+            let (host, rest) = rest
+                .split_once(']')
+                .ok_or(format!("Invalid iperf server \"{text}\""))?;
+            (host, rest.strip_prefix(':'))
+        }
+        //  More colons are an IPv6 address without a port
+        None => match text.split_once(':') {
+            Some((host, port)) if !port.contains(':') => (host, Some(port)),
+            _ => (text, None),
+        },
+    };
+
+    let port = port
+        .map(|port| port.parse::<u16>())
+        .transpose()
+        .map_err(|e| format!("Invalid port of iperf server \"{text}\": {e}"))?;
+    Ok(IperfServer {
+        host: host.to_string(),
+        port,
+        label: text.to_string(),
+    })
+}
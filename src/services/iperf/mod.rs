@@ -0,0 +1,3 @@
+pub mod config;
+mod model;
+pub mod service;
@@ -0,0 +1,43 @@
+use crate::measurement::ErrorCategory;
+use serde::Deserialize;
+
+/// Output of `iperf3 --json`, only the summary of the test is read.
+#[derive(Debug, Deserialize)]
+pub struct IperfOutput {
+    #[serde(default)]
+    pub end: IperfEnd,
+    pub error: Option<String>,
+}
+
+/// TCP tests report `sum_sent` and `sum_received`, UDP tests report `sum`.
+#[derive(Debug, Default, Deserialize)]
+pub struct IperfEnd {
+    pub sum_sent: Option<IperfSum>,
+    pub sum_received: Option<IperfSum>,
+    pub sum: Option<IperfSum>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IperfSum {
+    pub bytes: u64,
+    pub bits_per_second: f64,
+    pub retransmits: Option<u64>,
+    pub jitter_ms: Option<f64>,
+    pub lost_packets: Option<u64>,
+    pub packets: Option<u64>,
+    pub lost_percent: Option<f64>,
+}
+
+/// Guesses the reason of a failed test from the error reported by `iperf3`.
+pub fn error_category(error: &str) -> ErrorCategory {
+    let error = error.to_lowercase();
+    if error.contains("busy") {
+        ErrorCategory::Busy
+    } else if error.contains("unable to connect") || error.contains("unable to resolve") {
+        ErrorCategory::Connect
+    } else if error.contains("timeout") || error.contains("timed out") {
+        ErrorCategory::Timeout
+    } else {
+        ErrorCategory::Unknown
+    }
+}
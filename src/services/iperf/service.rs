@@ -0,0 +1,153 @@
+use crate::measurement::{ErrorCategory, Measurement};
+use crate::services::iperf::config::{IperfConfig, IperfProtocol, IperfServer};
+use crate::services::iperf::model::{error_category, IperfOutput};
+use crate::services::service::Service;
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{debug, warn};
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+/// Time given to `iperf3` to connect and exchange results on top of the test duration
+const RUN_MARGIN: Duration = Duration::from_secs(30);
+
+pub struct IperfService {
+    config: IperfConfig,
+    name: String,
+}
+
+impl IperfService {
+    pub fn new(config: IperfConfig, name: &str) -> Self {
+        Self {
+            config,
+            name: name.to_string(),
+        }
+    }
+
+    async fn measure(&self, server: &IperfServer) -> Measurement {
+        debug!(target: &self.name, "Testing server: {}", server.label);
+        let started = Utc::now();
+        let mut measurement = self.measurement(server).timestamp(started);
+        let output = match self.run(server).await {
+            Ok(output) => output,
+            Err((category, message)) => {
+                warn!(target: &self.name, "Failed to test server {} ({}): {}", server.label, category.as_str(), message);
+                return measurement.failure(category, message);
+            }
+        };
+        measurement = measurement.field("success", 1i64);
+
+        let end = output.end;
+        if let Some(sent) = &end.sum_sent {
+            measurement = measurement
+                .field("sent_bits_per_second", sent.bits_per_second)
+                .field("sent_bytes", sent.bytes as i64);
+            if let Some(retransmits) = sent.retransmits {
+                measurement = measurement.field("retransmits", retransmits as i64);
+            }
+        }
+
+        //  Throughput is what arrived at the receiving side
+        if let Some(received) = end.sum_received.as_ref().or(end.sum.as_ref()) {
+            measurement = measurement
+                .field("bits_per_second", received.bits_per_second)
+                .field("bytes", received.bytes as i64);
+        }
+
+        if let Some(sum) = &end.sum {
+            if let Some(jitter) = sum.jitter_ms {
+                measurement = measurement.field("jitter", jitter);
+            }
+            if let Some(lost_packets) = sum.lost_packets {
+                measurement = measurement.field("lost_packets", lost_packets as i64);
+            }
+            if let Some(packets) = sum.packets {
+                measurement = measurement.field("packets", packets as i64);
+            }
+            if let Some(lost_percent) = sum.lost_percent {
+                measurement = measurement.field("packet_loss", lost_percent / 100.0);
+            }
+        }
+
+        measurement
+    }
+
+    fn measurement(&self, server: &IperfServer) -> Measurement {
+        let direction = if self.config.reverse {
+            "download"
+        } else {
+            "upload"
+        };
+        Measurement::new(&self.name)
+            .tag("server", &server.label)
+            .tag("protocol", self.config.protocol.as_str())
+            .tag("direction", direction)
+    }
+
+    async fn run(&self, server: &IperfServer) -> Result<IperfOutput, (ErrorCategory, String)> {
+        let mut cmd = Command::new("iperf3");
+        cmd.arg("--client").arg(&server.host).arg("--json");
+        if let Some(port) = server.port {
+            cmd.arg("--port").arg(port.to_string());
+        }
+        cmd.arg("--time")
+            .arg(self.config.duration.as_secs().to_string())
+            .arg("--parallel")
+            .arg(self.config.parallel.to_string());
+        if let IperfProtocol::Udp = self.config.protocol {
+            cmd.arg("--udp");
+        }
+        if self.config.reverse {
+            cmd.arg("--reverse");
+        }
+        if let Some(bitrate) = &self.config.bitrate {
+            cmd.arg("-b").arg(bitrate);
+        }
+
+        //  A hanging `iperf3` does not outlive the limit, dropping the future kills it
+        cmd.kill_on_drop(true);
+        let limit = self.config.duration + RUN_MARGIN;
+        let output = timeout(limit, cmd.output())
+            .await
+            .map_err(|_| (ErrorCategory::Timeout, format!("Killed after {limit:?}")))?
+            .map_err(|e| {
+                (
+                    ErrorCategory::Command,
+                    format!("Failed to run command: {e}"),
+                )
+            })?;
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let output: IperfOutput = serde_json::from_str(&stdout).map_err(|e| {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let message = format!(
+                "Failed to parse output: {}, status: {}, stderr: {}",
+                e,
+                output.status,
+                stderr.trim()
+            );
+            (ErrorCategory::Parse, message)
+        })?;
+        if let Some(error) = output.error {
+            return Err((error_category(&error), error));
+        }
+        Ok(output)
+    }
+}
+
+#[async_trait]
+impl Service for IperfService {
+    fn name(&self) -> String {
+        format!("iperf/{}", self.name)
+    }
+
+    async fn execute(&self) -> anyhow::Result<Vec<Measurement>> {
+        //  Servers are tested in sequence, iperf3 servers accept a single test at a time
+        let mut measurements = Vec::new();
+        for server in &self.config.servers {
+            measurements.push(self.measure(server).await);
+        }
+        Ok(measurements)
+    }
+}
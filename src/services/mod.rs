@@ -1,3 +1,6 @@
+pub mod http;
+pub mod iperf;
 pub mod ping;
 pub mod service;
 pub mod speedtest;
+pub mod throughput;
@@ -1,50 +1,202 @@
-use clap::Args;
+use crate::config::{expand_instances, merge_instances};
+use clap::{Args, ValueEnum};
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::net::Ipv6Addr;
+use std::str::FromStr;
 use std::time::Duration;
 use url::Host;
 
-#[derive(Debug, Clone, Args)]
+#[derive(Debug, Clone, Default, Args, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct PartialPingConfig {
     #[arg(name = "PING_CRON", long = "ping-cron", env = "PING_CRON")]
     pub cron: Option<String>,
-    #[arg(name = "PING_HOSTS", long = "ping-hosts", env = "PING_HOSTS")]
-    pub hosts: Option<String>,
+    #[arg(
+        name = "PING_HOSTS",
+        long = "ping-hosts",
+        env = "PING_HOSTS",
+        value_delimiter = ','
+    )]
+    #[serde(default, deserialize_with = "deserialize_hosts")]
+    pub hosts: Option<Vec<PartialPingHost>>,
     #[arg(
         name = "PING_TIMEOUT",
         long = "ping-timeout",
         env = "PING_TIMEOUT",
-        default_value = "1000",
-        help = "[milliseconds]"
+        help = "Time to wait for every reply [milliseconds] [default: 1000]"
     )]
-    pub timeout: u64,
+    pub timeout: Option<u64>,
+    #[arg(
+        name = "PING_DEADLINE",
+        long = "ping-deadline",
+        env = "PING_DEADLINE",
+        help = "Time limit of pinging a single host [milliseconds]"
+    )]
+    pub deadline: Option<u64>,
     #[arg(
         name = "PING_BYTES",
         long = "ping-bytes",
         env = "PING_BYTES",
-        default_value = "32",
-        help = "[bytes]"
+        help = "[bytes] [default: 32]"
     )]
-    pub bytes: usize,
+    pub bytes: Option<usize>,
     #[arg(
         name = "PING_COUNT",
         long = "ping-count",
         env = "PING_COUNT",
-        default_value = "5"
+        help = "[default: 5]"
     )]
-    pub count: usize,
+    pub count: Option<usize>,
+    #[arg(
+        value_enum,
+        name = "PING_METHOD",
+        long = "ping-method",
+        env = "PING_METHOD",
+        help = "[default: command]"
+    )]
+    pub method: Option<PingMethod>,
+    #[arg(
+        name = "PING_SAMPLES",
+        long = "ping-samples",
+        env = "PING_SAMPLES",
+        help = "Write every reply as a separate point [default: false]"
+    )]
+    pub samples: Option<bool>,
+    #[arg(
+        name = "PING_CONCURRENCY",
+        long = "ping-concurrency",
+        env = "PING_CONCURRENCY",
+        help = "Number of hosts pinged at the same time [default: 1]"
+    )]
+    pub concurrency: Option<usize>,
+    #[arg(
+        value_enum,
+        name = "PING_FAMILY",
+        long = "ping-family",
+        env = "PING_FAMILY",
+        help = "[default: any]"
+    )]
+    pub family: Option<PingFamily>,
+    #[arg(skip)]
+    #[serde(default)]
+    pub instances: BTreeMap<String, PartialPingConfig>,
+}
+
+impl PartialPingConfig {
+    /// Values of `self` take precedence over values of `other`.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            cron: self.cron.or(other.cron),
+            hosts: self.hosts.or(other.hosts),
+            timeout: self.timeout.or(other.timeout),
+            deadline: self.deadline.or(other.deadline),
+            bytes: self.bytes.or(other.bytes),
+            count: self.count.or(other.count),
+            method: self.method.or(other.method),
+            samples: self.samples.or(other.samples),
+            concurrency: self.concurrency.or(other.concurrency),
+            family: self.family.or(other.family),
+            instances: merge_instances(self.instances, other.instances),
+        }
+    }
+}
+
+/// Host with optional overrides of the instance settings, only configurable in the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartialPingHost {
+    pub host: String,
+    pub label: Option<String>,
+    pub timeout: Option<u64>,
+    pub deadline: Option<u64>,
+    pub bytes: Option<usize>,
+    pub count: Option<usize>,
+    pub family: Option<PingFamily>,
+}
+
+impl From<&str> for PartialPingHost {
+    fn from(host: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            label: None,
+            timeout: None,
+            deadline: None,
+            bytes: None,
+            count: None,
+            family: None,
+        }
+    }
+}
+
+impl FromStr for PartialPingHost {
+    type Err = Infallible;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from(s))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct PingConfig {
     pub cron: String,
-    pub hosts: Vec<Host>,
+    pub hosts: Vec<PingHost>,
+    pub method: PingMethod,
+    pub samples: bool,
+    pub concurrency: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PingHost {
+    pub host: Host,
+    /// Used as the `host` tag, defaults to the host itself
+    pub label: String,
+    /// Time to wait for every reply
     pub timeout: Duration,
+    /// Time limit of the whole run, replies not received until then are lost
+    pub deadline: Option<Duration>,
     pub bytes: usize,
     pub count: usize,
+    pub family: PingFamily,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PingMethod {
+    /// Runs the system `ping` binary and parses its output
+    Command,
+    /// Sends ICMP echo requests directly from the process
+    Native,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PingFamily {
+    /// Lets the resolver choose the address, points are not tagged with `family`
+    Any,
+    V4,
+    V6,
+    /// Pings IPv4 and IPv6 addresses separately
+    Both,
+}
+
+impl TryFrom<PartialPingConfig> for BTreeMap<String, PingConfig> {
+    type Error = String;
+    fn try_from(mut config: PartialPingConfig) -> Result<Self, Self::Error> {
+        let instances = std::mem::take(&mut config.instances);
+        expand_instances("ping", config, instances, "Ping")
+    }
 }
 
 impl TryFrom<PartialPingConfig> for Option<PingConfig> {
     type Error = String;
     fn try_from(config: PartialPingConfig) -> Result<Self, Self::Error> {
+        if !config.instances.is_empty() {
+            return Err("Instances can not be nested".to_string());
+        }
         if config.cron.is_none() && config.hosts.is_none() {
             return Ok(None);
         }
@@ -52,27 +204,130 @@ impl TryFrom<PartialPingConfig> for Option<PingConfig> {
         let cron = config
             .cron
             .ok_or("PING_CRON is required for \"PING_\" parameters")?;
+        let timeout = config.timeout.unwrap_or(1000);
+        let deadline = config.deadline;
+        let bytes = config.bytes.unwrap_or(32);
+        let count = config.count.unwrap_or(5);
+        let family = config.family.unwrap_or(PingFamily::Any);
         let hosts = config
             .hosts
-            .ok_or("PING_HOSTS is required for \"PING_\" parameters")
-            .map(|h| parse_hosts(&h))??;
-        let timeout = Duration::from_millis(config.timeout);
-        let bytes = config.bytes;
-        let count = config.count;
+            .ok_or("PING_HOSTS is required for \"PING_\" parameters")?
+            .into_iter()
+            .map(|host| {
+                let count = host.count.unwrap_or(count);
+                if count == 0 {
+                    return Err(format!(
+                        "Ping count of {} must be greater than 0",
+                        host.host
+                    ));
+                }
+                Ok(PingHost {
+                    label: host.label.unwrap_or(host.host.clone()),
+                    host: parse_host(&host.host)?,
+                    timeout: Duration::from_millis(host.timeout.unwrap_or(timeout)),
+                    deadline: host.deadline.or(deadline).map(Duration::from_millis),
+                    bytes: host.bytes.unwrap_or(bytes),
+                    count,
+                    family: host.family.unwrap_or(family),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let method = config.method.unwrap_or(PingMethod::Command);
+        let samples = config.samples.unwrap_or(false);
+        let concurrency = config.concurrency.unwrap_or(1);
+        if concurrency == 0 {
+            return Err("PING_CONCURRENCY must be greater than 0".to_string());
+        }
 
         Ok(Some(PingConfig {
             cron,
             hosts,
-            timeout,
-            bytes,
-            count,
+            method,
+            samples,
+            concurrency,
         }))
     }
 }
 
-fn parse_hosts(text: &str) -> Result<Vec<Host>, String> {
-    text.split(',')
-        .map(|s| Host::parse(s))
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| e.to_string())
+/// Same as `Host::parse` but also accepts IPv6 addresses without brackets.
+fn parse_host(text: &str) -> Result<Host, String> {
+    if let Ok(address) = text.parse::<Ipv6Addr>() {
+        return Ok(Host::Ipv6(address));
+    }
+    Host::parse(text).map_err(|e| format!("Invalid host \"{}\": {}", text, e))
+}
+
+/// Accepts a comma separated string or a list of hosts, either plain or with their own settings.
+///
+/// Values are dispatched by their type, so errors of host settings name the offending key.
+fn deserialize_hosts<'de, D>(deserializer: D) -> Result<Option<Vec<PartialPingHost>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct HostsVisitor;
+
+    impl<'de> Visitor<'de> for HostsVisitor {
+        type Value = Vec<PartialPingHost>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a comma separated string or a list of hosts")
+        }
+
+        fn visit_str<E>(self, text: &str) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(text.split(',').map(PartialPingHost::from).collect())
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut hosts = Vec::new();
+            while let Some(HostsEntry(host)) = seq.next_element()? {
+                hosts.push(host);
+            }
+            Ok(hosts)
+        }
+    }
+
+    struct HostsEntry(PartialPingHost);
+
+    impl<'de> Deserialize<'de> for HostsEntry {
+        fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            deserializer
+                .deserialize_any(HostsEntryVisitor)
+                .map(HostsEntry)
+        }
+    }
+
+    struct HostsEntryVisitor;
+
+    impl<'de> Visitor<'de> for HostsEntryVisitor {
+        type Value = PartialPingHost;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a host or a table of host settings")
+        }
+
+        fn visit_str<E>(self, text: &str) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(PartialPingHost::from(text))
+        }
+
+        fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            PartialPingHost::deserialize(MapAccessDeserializer::new(map))
+        }
+    }
+
+    deserializer.deserialize_any(HostsVisitor).map(Some)
 }
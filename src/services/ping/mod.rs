@@ -1,3 +1,5 @@
 pub mod config;
 mod model;
+mod native;
+mod resolve;
 pub mod service;
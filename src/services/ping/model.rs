@@ -1,5 +1,6 @@
+use crate::measurement::ErrorCategory;
 use regex::Regex;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::process::Command;
 use url::Host;
 
@@ -8,42 +9,201 @@ pub struct PingOutput {
     pub rtt_min: f64,
     pub rtt_avg: f64,
     pub rtt_max: f64,
+    pub rtt_mdev: f64,
+    /// Round trip times of received replies, in the order they arrived
+    pub samples: Vec<f64>,
 }
 
+impl PingOutput {
+    /// Statistics of the received replies, packet loss is relative to `count`.
+    pub fn from_samples(count: usize, samples: Vec<f64>) -> Self {
+        let received = samples.len();
+        let mut output = PingOutput {
+            packet_loss: (count - received.min(count)) as f64 / count as f64,
+            rtt_min: 0.0,
+            rtt_avg: 0.0,
+            rtt_max: 0.0,
+            rtt_mdev: mean_deviation(&samples),
+            samples: Vec::new(),
+        };
+        if received > 0 {
+            output.rtt_min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+            output.rtt_max = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            output.rtt_avg = samples.iter().sum::<f64>() / received as f64;
+        }
+        output.samples = samples;
+        output
+    }
+
+    /// Mean absolute difference between consecutive round trip times.
+    pub fn jitter(&self) -> f64 {
+        if self.samples.len() < 2 {
+            return 0.0;
+        }
+
+        let total: f64 = self.samples.windows(2).map(|w| (w[1] - w[0]).abs()).sum();
+        total / (self.samples.len() - 1) as f64
+    }
+
+    pub fn rtt_median(&self) -> f64 {
+        self.rtt_percentile(50.0)
+    }
+
+    pub fn rtt_p95(&self) -> f64 {
+        self.rtt_percentile(95.0)
+    }
+
+    /// Nearest-rank percentile of the samples.
+    fn rtt_percentile(&self, percentile: f64) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+
+        let mut sorted = self.samples.clone();
+        sorted.sort_by(f64::total_cmp);
+        let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+}
+
+/// Mean deviation of round trip times, computed the same way as `ping` does.
+pub fn mean_deviation(samples: &[f64]) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+
+    let count = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / count;
+    let mean_square = samples.iter().map(|s| s * s).sum::<f64>() / count;
+    (mean_square - mean * mean).max(0.0).sqrt()
+}
+
+/// `timeout` is the time to wait for every reply, `deadline` limits the whole run.
+///
+/// Packet loss is relative to `count`, replies that did not arrive before the deadline are lost.
 pub async fn run_ping(
     target: &Host,
     bytes: usize,
     count: usize,
     timeout: Duration,
-) -> anyhow::Result<PingOutput> {
-    let output = create_command(&target.to_string(), bytes, count, timeout)
-        .output()
-        .await?;
-    let stdout = String::from_utf8(output.stdout)?;
-    parse_output(&stdout)
+    deadline: Option<Duration>,
+) -> Result<PingOutput, (ErrorCategory, String)> {
+    //  IPv6 addresses are displayed in brackets by `Host`, which `ping` does not accept
+    let target = match target {
+        Host::Ipv6(address) => address.to_string(),
+        target => target.to_string(),
+    };
+    let mut cmd = create_command(&target, bytes, count, timeout, deadline);
+    #[cfg(target_os = "windows")]
+    if let Some(deadline) = deadline {
+        return run_until_deadline(cmd, count, deadline).await;
+    }
+
+    //  Replies are compared against the deadline in seconds since the epoch, as printed by `ping -D`
+    let cutoff = deadline.map(|deadline| {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        (started + deadline).as_secs_f64()
+    });
+    let output = cmd.output().await.map_err(|e| {
+        (
+            ErrorCategory::Command,
+            format!("Failed to run command: {e}"),
+        )
+    })?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    parse_output(&stdout, count, cutoff).map_err(|e| {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let category = error_category(&format!("{stdout}\n{stderr}"));
+        match stderr.trim() {
+            "" => (category, e.to_string()),
+            stderr => (category, format!("{e}: {stderr}")),
+        }
+    })
+}
+
+/// Output without statistics is unparseable, unless `ping` could not resolve the host.
+fn error_category(output: &str) -> ErrorCategory {
+    let output = output.to_lowercase();
+    if [
+        "name or service not known",
+        "temporary failure in name resolution",
+        "unknown host",
+        "could not find host",
+    ]
+    .iter()
+    .any(|message| output.contains(message))
+    {
+        ErrorCategory::Dns
+    } else {
+        ErrorCategory::Parse
+    }
 }
 
 #[cfg(target_os = "linux")]
-fn create_command(target: &str, bytes: usize, count: usize, timeout: Duration) -> Command {
+fn create_command(
+    target: &str,
+    bytes: usize,
+    count: usize,
+    timeout: Duration,
+    deadline: Option<Duration>,
+) -> Command {
     let mut cmd = Command::new("ping");
     cmd.arg("-c").arg(count.to_string());
     cmd.arg("-s").arg(bytes.to_string());
-    cmd.arg("-w").arg(timeout.as_secs().to_string());
+    cmd.arg("-W").arg(format!("{:.3}", timeout.as_secs_f64()));
+    //  Deadline is accepted only in whole seconds, replies are timestamped
+    //  so the ones after a sub-second deadline are not counted
+    if let Some(deadline) = deadline {
+        let seconds = deadline.as_secs_f64().ceil().max(1.0);
+        cmd.arg("-w").arg(format!("{seconds:.0}")).arg("-D");
+    }
     cmd.arg(target);
     cmd
 }
 
+/// Replies received after `cutoff` are lost, the statistics are computed from the rest.
 #[cfg(target_os = "linux")]
-fn parse_output(output: &str) -> anyhow::Result<PingOutput> {
-    let packet_loss_re = Regex::new(
-        r"[0-9]+ packets transmitted, [0-9]+ received, ([0-9]+)% packet loss, time [0-9]+ms",
-    )?;
-    let rtt_re = Regex::new(r"rtt min/avg/max/mdev = ([0-9.]+)/([0-9.]+)/([0-9.]+)/[0-9.]+ ms")?;
-    parse_any_output(output, &packet_loss_re, &rtt_re, 1, 2, 3)
+fn parse_output(output: &str, count: usize, cutoff: Option<f64>) -> anyhow::Result<PingOutput> {
+    if let Some(cutoff) = cutoff {
+        let reply_re = Regex::new(r"^\[([0-9.]+)\] .*bytes from .* time=([0-9.]+) ms")?;
+        let mut samples = Vec::new();
+        for line in output.lines().map(str::trim) {
+            if let Some(captures) = reply_re.captures(line) {
+                if captures[1].parse::<f64>()? <= cutoff {
+                    samples.push(captures[2].parse()?);
+                }
+            }
+        }
+        if !output.contains("packets transmitted") {
+            return Err(anyhow::anyhow!("Missing ping statistics"));
+        }
+        return Ok(PingOutput::from_samples(count, samples));
+    }
+
+    let statistics_re = Regex::new(r"([0-9]+) packets transmitted, ([0-9]+) received")?;
+    let rtt_re = Regex::new(r"rtt min/avg/max/mdev = ([0-9.]+)/([0-9.]+)/([0-9.]+)/([0-9.]+) ms")?;
+    let reply_re = Regex::new(r"bytes from .* time=([0-9.]+) ms")?;
+    parse_any_output(
+        output,
+        count,
+        &statistics_re,
+        &rtt_re,
+        &reply_re,
+        [1, 2, 3, 4],
+    )
 }
 
+/// Windows `ping` has no deadline option, only the timeout of every reply, see `run_until_deadline`.
 #[cfg(target_os = "windows")]
-fn create_command(target: &str, bytes: usize, count: usize, timeout: Duration) -> Command {
+fn create_command(
+    target: &str,
+    bytes: usize,
+    count: usize,
+    timeout: Duration,
+    _deadline: Option<Duration>,
+) -> Command {
     let mut cmd = Command::new("ping");
     cmd.arg("-n").arg(count.to_string());
     cmd.arg("-l").arg(bytes.to_string());
@@ -52,43 +212,173 @@ fn create_command(target: &str, bytes: usize, count: usize, timeout: Duration) -
     cmd
 }
 
+/// Kills `ping` at the deadline, the replies printed until then are counted and the rest is lost.
+#[cfg(target_os = "windows")]
+async fn run_until_deadline(
+    mut cmd: Command,
+    count: usize,
+    deadline: Duration,
+) -> Result<PingOutput, (ErrorCategory, String)> {
+    use std::process::Stdio;
+    use tokio::io::AsyncReadExt;
+
+    //  Child is killed when it is dropped after the deadline
+    cmd.stdout(Stdio::piped()).kill_on_drop(true);
+    let mut child = cmd.spawn().map_err(|e| {
+        (
+            ErrorCategory::Command,
+            format!("Failed to run command: {e}"),
+        )
+    })?;
+    let mut stdout = child.stdout.take().ok_or((
+        ErrorCategory::Command,
+        "Output of command is not captured".to_string(),
+    ))?;
+
+    //  Output read before the deadline stays in the buffer when the read is cut
+    let mut buffer = Vec::new();
+    let finished = tokio::time::timeout(deadline, stdout.read_to_end(&mut buffer)).await;
+    let output = String::from_utf8_lossy(&buffer);
+    match finished {
+        Ok(Ok(_)) => {
+            parse_output(&output, count, None).map_err(|e| (error_category(&output), e.to_string()))
+        }
+        Ok(Err(e)) => Err((
+            ErrorCategory::Command,
+            format!("Failed to read output: {e}"),
+        )),
+        Err(_) => {
+            let reply_re =
+                Regex::new(REPLY_PATTERN).map_err(|e| (ErrorCategory::Parse, e.to_string()))?;
+            let samples = output
+                .lines()
+                .filter_map(|line| reply_re.captures(line)?[1].parse().ok())
+                .collect();
+            Ok(PingOutput::from_samples(count, samples))
+        }
+    }
+}
+
 #[cfg(target_os = "windows")]
-fn parse_output(output: &str) -> anyhow::Result<PingOutput> {
-    let packet_loss_re = Regex::new(
-        r"Packets: Sent = [0-9]+, Received = [0-9]+, Lost = [0-9]+ \(([0-9]+)% loss\),",
-    )?;
+const REPLY_PATTERN: &str = r"Reply from .* time[=<]([0-9]+)ms";
+
+#[cfg(target_os = "windows")]
+fn parse_output(output: &str, count: usize, _cutoff: Option<f64>) -> anyhow::Result<PingOutput> {
+    let statistics_re = Regex::new(r"Packets: Sent = ([0-9]+), Received = ([0-9]+),")?;
     let rtt_re = Regex::new(r"Minimum = ([0-9]+)ms, Maximum = ([0-9]+)ms, Average = ([0-9]+)ms")?;
-    parse_any_output(output, &packet_loss_re, &rtt_re, 1, 3, 2)
+    let reply_re = Regex::new(REPLY_PATTERN)?;
+    //  Windows does not report mean deviation, it is computed from the replies
+    parse_any_output(
+        output,
+        count,
+        &statistics_re,
+        &rtt_re,
+        &reply_re,
+        [1, 3, 2, 0],
+    )
 }
 
+/// `statistics_re` captures number of sent and received packets,
+/// `groups` are capture groups of `rtt_re` for min, avg, max and mdev, `0` when not present.
 fn parse_any_output(
     output: &str,
-    packet_loss_re: &Regex,
+    count: usize,
+    statistics_re: &Regex,
     rtt_re: &Regex,
-    min: usize,
-    avg: usize,
-    max: usize,
+    reply_re: &Regex,
+    groups: [usize; 4],
 ) -> anyhow::Result<PingOutput> {
-    let mut packet_loss = 0.0;
+    let [min, avg, max, mdev] = groups;
+    let mut received = None;
     let mut rtt_min = 0.0;
     let mut rtt_avg = 0.0;
     let mut rtt_max = 0.0;
+    let mut rtt_mdev = None;
+    let mut samples = Vec::new();
 
     for line in output.lines().map(str::trim) {
-        if let Some(captures) = packet_loss_re.captures(line) {
-            packet_loss = captures[1].parse()?;
+        if let Some(captures) = statistics_re.captures(line) {
+            received = Some(captures[2].parse::<usize>()?);
         } else if let Some(captures) = rtt_re.captures(line) {
             rtt_min = captures[min].parse()?;
             rtt_avg = captures[avg].parse()?;
             rtt_max = captures[max].parse()?;
+            if mdev > 0 {
+                rtt_mdev = Some(captures[mdev].parse()?);
+            }
+        } else if let Some(captures) = reply_re.captures(line) {
+            samples.push(captures[1].parse()?);
         }
     }
 
-    packet_loss /= 100.0;
+    let received = received.ok_or(anyhow::anyhow!("Missing ping statistics"))?;
+    let packet_loss = (count - received.min(count)) as f64 / count as f64;
     Ok(PingOutput {
         packet_loss,
         rtt_min,
         rtt_avg,
         rtt_max,
+        rtt_mdev: rtt_mdev.unwrap_or_else(|| mean_deviation(&samples)),
+        samples,
     })
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    /// Output of `ping -D -c 3 -w 3`, replies are one second apart.
+    const TIMESTAMPED_OUTPUT: &str = "\
+PING 1.1.1.1 (1.1.1.1) 56(84) bytes of data.
+[1705744800.123456] 64 bytes from 1.1.1.1: icmp_seq=1 ttl=57 time=10.5 ms
+[1705744801.124567] 64 bytes from 1.1.1.1: icmp_seq=2 ttl=57 time=11.5 ms
+[1705744802.125678] 64 bytes from 1.1.1.1: icmp_seq=3 ttl=57 time=12.5 ms
+
+--- 1.1.1.1 ping statistics ---
+3 packets transmitted, 3 received, 0% packet loss, time 2003ms
+rtt min/avg/max/mdev = 10.500/11.500/12.500/0.816 ms
+";
+
+    #[test]
+    fn replies_after_the_cutoff_are_lost() {
+        let output = parse_output(TIMESTAMPED_OUTPUT, 3, Some(1705744801.5)).unwrap();
+
+        assert_eq!(output.samples, vec![10.5, 11.5]);
+        assert!((output.packet_loss - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(output.rtt_min, 10.5);
+        assert_eq!(output.rtt_avg, 11.0);
+        assert_eq!(output.rtt_max, 11.5);
+    }
+
+    #[test]
+    fn reply_at_the_cutoff_is_counted() {
+        let output = parse_output(TIMESTAMPED_OUTPUT, 3, Some(1705744802.125678)).unwrap();
+
+        assert_eq!(output.samples.len(), 3);
+        assert_eq!(output.packet_loss, 0.0);
+    }
+
+    #[test]
+    fn loss_is_relative_to_count() {
+        let output = parse_output(TIMESTAMPED_OUTPUT, 4, Some(1705744900.0)).unwrap();
+
+        assert_eq!(output.packet_loss, 0.25);
+    }
+
+    #[test]
+    fn output_without_statistics_is_rejected() {
+        let output = "[1705744800.123456] 64 bytes from 1.1.1.1: icmp_seq=1 ttl=57 time=10.5 ms\n";
+
+        assert!(parse_output(output, 1, Some(1705744801.0)).is_err());
+        assert!(parse_output("ping: bad.host: Name or service not known", 1, None).is_err());
+    }
+
+    #[test]
+    fn statistics_are_read_without_cutoff() {
+        let output = parse_output(TIMESTAMPED_OUTPUT, 3, None).unwrap();
+
+        assert_eq!(output.packet_loss, 0.0);
+        assert_eq!(output.rtt_avg, 11.5);
+        assert_eq!(output.rtt_mdev, 0.816);
+    }
+}
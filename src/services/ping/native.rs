@@ -0,0 +1,225 @@
+use crate::measurement::ErrorCategory;
+use crate::services::ping::model::PingOutput;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+use std::io;
+use std::io::{ErrorKind, Read};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+use url::Host;
+
+const ICMPV4_ECHO_REQUEST: u8 = 8;
+const ICMPV4_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+const ICMP_HEADER_SIZE: usize = 8;
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Distinguishes replies of concurrent probes on raw sockets.
+static NEXT_IDENTIFIER: AtomicU16 = AtomicU16::new(0);
+
+/// Sends ICMP echo requests from the process itself, without the `ping` binary.
+///
+/// Unprivileged datagram ICMP sockets are tried first, raw sockets are used as a fallback.
+pub async fn run_native_ping(
+    target: &Host,
+    bytes: usize,
+    count: usize,
+    timeout: Duration,
+    deadline: Option<Duration>,
+) -> Result<PingOutput, (ErrorCategory, String)> {
+    let target = target.clone();
+    tokio::task::spawn_blocking(move || {
+        let address = resolve(&target).map_err(|e| (ErrorCategory::Dns, e.to_string()))?;
+        let prober = Prober::open(address).map_err(|e| {
+            let message = format!("Failed to open ICMP socket: {e}");
+            (ErrorCategory::Socket, message)
+        })?;
+        prober
+            .run(bytes, count, timeout, deadline)
+            .map_err(|e| (ErrorCategory::Socket, e.to_string()))
+    })
+    .await
+    .map_err(|e| (ErrorCategory::Unknown, e.to_string()))?
+}
+
+fn resolve(target: &Host) -> anyhow::Result<IpAddr> {
+    match target {
+        Host::Ipv4(address) => Ok(IpAddr::V4(*address)),
+        Host::Ipv6(address) => Ok(IpAddr::V6(*address)),
+        Host::Domain(domain) => (domain.as_str(), 0)
+            .to_socket_addrs()?
+            .next()
+            .map(|address| address.ip())
+            .ok_or_else(|| anyhow::anyhow!("No address found for {}", domain)),
+    }
+}
+
+struct Prober {
+    socket: Socket,
+    address: IpAddr,
+    raw: bool,
+    identifier: u16,
+}
+
+impl Prober {
+    fn open(address: IpAddr) -> anyhow::Result<Self> {
+        let (domain, protocol) = match address {
+            IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+            IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+        };
+
+        let (socket, raw) = match Socket::new(domain, Type::DGRAM, Some(protocol)) {
+            Ok(socket) => (socket, false),
+            Err(_) => (Socket::new(domain, Type::RAW, Some(protocol))?, true),
+        };
+        socket.connect(&SockAddr::from(SocketAddr::new(address, 0)))?;
+
+        Ok(Self {
+            socket,
+            address,
+            raw,
+            identifier: (std::process::id() as u16)
+                ^ NEXT_IDENTIFIER.fetch_add(1, Ordering::Relaxed),
+        })
+    }
+
+    /// Requests not sent or not answered before the deadline are counted as lost.
+    fn run(
+        &self,
+        bytes: usize,
+        count: usize,
+        timeout: Duration,
+        deadline: Option<Duration>,
+    ) -> anyhow::Result<PingOutput> {
+        let deadline = deadline.map(|deadline| Instant::now() + deadline);
+        let mut rtts = Vec::with_capacity(count);
+        for sequence in 0..count {
+            let started = Instant::now();
+            let timeout = match deadline {
+                Some(deadline) => timeout.min(deadline.saturating_duration_since(started)),
+                None => timeout,
+            };
+            if timeout.is_zero() {
+                break;
+            }
+
+            if let Some(rtt) = self.probe(sequence as u16, bytes, timeout)? {
+                rtts.push(rtt.as_secs_f64() * 1000.0);
+            }
+
+            if sequence + 1 < count {
+                std::thread::sleep(PING_INTERVAL.saturating_sub(started.elapsed()));
+            }
+        }
+
+        Ok(PingOutput::from_samples(count, rtts))
+    }
+
+    /// Returns round trip time of a single echo request or `None` when no reply came in time.
+    fn probe(
+        &self,
+        sequence: u16,
+        bytes: usize,
+        timeout: Duration,
+    ) -> anyhow::Result<Option<Duration>> {
+        let request = self.echo_request(sequence, bytes);
+        let sent = Instant::now();
+        match self.socket.send(&request) {
+            Ok(_) => {}
+            Err(e) if is_unreachable(&e) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut buffer = vec![0u8; bytes + ICMP_HEADER_SIZE + 128];
+        loop {
+            let remaining = timeout.saturating_sub(sent.elapsed());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(remaining))?;
+
+            let received = match (&self.socket).read(&mut buffer) {
+                Ok(received) => received,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(None);
+                }
+                Err(e) if is_unreachable(&e) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+
+            if self.is_echo_reply(&buffer[..received], sequence) {
+                return Ok(Some(sent.elapsed()));
+            }
+        }
+    }
+
+    fn echo_request(&self, sequence: u16, bytes: usize) -> Vec<u8> {
+        let kind = match self.address {
+            IpAddr::V4(_) => ICMPV4_ECHO_REQUEST,
+            IpAddr::V6(_) => ICMPV6_ECHO_REQUEST,
+        };
+
+        let mut packet = vec![0u8; ICMP_HEADER_SIZE + bytes];
+        packet[0] = kind;
+        packet[4..6].copy_from_slice(&self.identifier.to_be_bytes());
+        packet[6..8].copy_from_slice(&sequence.to_be_bytes());
+        for (i, byte) in packet[ICMP_HEADER_SIZE..].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        //  ICMPv6 checksum is always computed by the kernel
+        if self.address.is_ipv4() {
+            let checksum = checksum(&packet);
+            packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+        }
+        packet
+    }
+
+    fn is_echo_reply(&self, packet: &[u8], sequence: u16) -> bool {
+        //  Raw IPv4 sockets receive the IP header as well
+        let packet = match self.address {
+            IpAddr::V4(_) if self.raw => {
+                let header = packet.first().map(|b| (b & 0x0f) as usize * 4).unwrap_or(0);
+                packet.get(header..).unwrap_or_default()
+            }
+            _ => packet,
+        };
+        if packet.len() < ICMP_HEADER_SIZE {
+            return false;
+        }
+
+        let reply = match self.address {
+            IpAddr::V4(_) => ICMPV4_ECHO_REPLY,
+            IpAddr::V6(_) => ICMPV6_ECHO_REPLY,
+        };
+        //  Identifier of datagram sockets is assigned by the kernel
+        let identifier = u16::from_be_bytes([packet[4], packet[5]]);
+        packet[0] == reply
+            && (!self.raw || identifier == self.identifier)
+            && u16::from_be_bytes([packet[6], packet[7]]) == sequence
+    }
+}
+
+/// Errors reported by connected sockets when an ICMP error, e.g. destination unreachable, arrives.
+fn is_unreachable(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::ConnectionRefused | ErrorKind::HostUnreachable | ErrorKind::NetworkUnreachable
+    )
+}
+
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum = data
+        .chunks(2)
+        .map(|chunk| match chunk {
+            [high, low] => u32::from(u16::from_be_bytes([*high, *low])),
+            [high] => u32::from(*high) << 8,
+            _ => 0,
+        })
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
@@ -0,0 +1,45 @@
+use crate::services::ping::config::PingFamily;
+use std::net::IpAddr;
+use tokio::net::lookup_host;
+use url::Host;
+
+/// Resolves the host to one address of every requested family.
+///
+/// Returns the family tag together with the address, or the reason why there is none.
+pub async fn resolve_families(
+    host: &Host,
+    family: PingFamily,
+) -> Vec<(&'static str, anyhow::Result<Host>)> {
+    let families: &[&'static str] = match family {
+        PingFamily::Any => return vec![],
+        PingFamily::V4 => &["v4"],
+        PingFamily::V6 => &["v6"],
+        PingFamily::Both => &["v4", "v6"],
+    };
+
+    let addresses = match host {
+        Host::Ipv4(address) => Ok(vec![IpAddr::V4(*address)]),
+        Host::Ipv6(address) => Ok(vec![IpAddr::V6(*address)]),
+        Host::Domain(domain) => lookup_host((domain.as_str(), 0))
+            .await
+            .map(|addresses| addresses.map(|a| a.ip()).collect()),
+    };
+
+    families
+        .iter()
+        .map(|&family| {
+            let address = match &addresses {
+                Ok(addresses) => addresses
+                    .iter()
+                    .find(|a| a.is_ipv4() == (family == "v4"))
+                    .map(|address| match address {
+                        IpAddr::V4(address) => Host::Ipv4(*address),
+                        IpAddr::V6(address) => Host::Ipv6(*address),
+                    })
+                    .ok_or_else(|| anyhow::anyhow!("No IP{} address found for {}", family, host)),
+                Err(e) => Err(anyhow::anyhow!("Failed to resolve {}: {}", host, e)),
+            };
+            (family, address)
+        })
+        .collect()
+}
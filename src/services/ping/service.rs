@@ -1,26 +1,132 @@
-use crate::influxdb::InfluxDB;
-use crate::services::ping::config::PingConfig;
-use crate::services::ping::model::run_ping;
+use crate::measurement::{ErrorCategory, Measurement};
+use crate::services::ping::config::{PingConfig, PingFamily, PingHost, PingMethod};
+use crate::services::ping::model::{run_ping, PingOutput};
+use crate::services::ping::native::run_native_ping;
+use crate::services::ping::resolve::resolve_families;
 use crate::services::service::Service;
 use async_trait::async_trait;
-use influxdb2::models::DataPoint;
-use log::debug;
-use std::sync::Arc;
+use chrono::Utc;
+use futures::{stream, StreamExt};
+use log::{debug, warn};
+use url::Host;
 
 pub struct PingService {
-    db: Arc<InfluxDB>,
     config: PingConfig,
     name: String,
 }
 
 impl PingService {
-    pub fn new(db: Arc<InfluxDB>, config: PingConfig, name: &str) -> Self {
+    pub fn new(config: PingConfig, name: &str) -> Self {
         Self {
-            db,
             config,
             name: name.to_string(),
         }
     }
+
+    async fn measure(&self, host: &PingHost) -> Vec<Measurement> {
+        if host.family == PingFamily::Any {
+            return self.measure_target(host, &host.host, None).await;
+        }
+
+        let mut measurements = Vec::new();
+        for (family, target) in resolve_families(&host.host, host.family).await {
+            match target {
+                Ok(target) => {
+                    measurements.extend(self.measure_target(host, &target, Some(family)).await);
+                }
+                Err(e) => {
+                    let measurement = self
+                        .failure(host, Some(family), ErrorCategory::Dns, e.to_string())
+                        .timestamp(Utc::now());
+                    measurements.push(measurement);
+                }
+            }
+        }
+        measurements
+    }
+
+    async fn measure_target(
+        &self,
+        host: &PingHost,
+        target: &Host,
+        family: Option<&str>,
+    ) -> Vec<Measurement> {
+        debug!(target: &self.name, "Pinging host: {} ({})", host.host, target);
+        let started = Utc::now();
+        let ping = match self.ping(host, target).await {
+            Ok(ping) => ping,
+            Err((category, message)) => {
+                return vec![self
+                    .failure(host, family, category, message)
+                    .timestamp(started)];
+            }
+        };
+
+        let mut measurements = Vec::new();
+        let measurement = self
+            .measurement(&self.name, host, family)
+            .timestamp(started)
+            .field("success", 1i64)
+            .field("packet_loss", ping.packet_loss)
+            .field("rtt_min", ping.rtt_min)
+            .field("rtt_avg", ping.rtt_avg)
+            .field("rtt_max", ping.rtt_max)
+            .field("rtt_mdev", ping.rtt_mdev)
+            .field("rtt_median", ping.rtt_median())
+            .field("rtt_p95", ping.rtt_p95())
+            .field("jitter", ping.jitter());
+        measurements.push(measurement);
+
+        if self.config.samples {
+            let name = format!("{}_samples", self.name);
+            for (i, rtt) in ping.samples.iter().enumerate() {
+                let measurement = self
+                    .measurement(&name, host, family)
+                    .tag("sample", i.to_string())
+                    .field("rtt", *rtt)
+                    .timestamp(started);
+                measurements.push(measurement);
+            }
+        }
+        measurements
+    }
+
+    /// Host that could not be pinged at all has lost every packet.
+    fn failure(
+        &self,
+        host: &PingHost,
+        family: Option<&str>,
+        category: ErrorCategory,
+        message: String,
+    ) -> Measurement {
+        warn!(target: &self.name, "Failed to ping host {} ({}): {}", host.host, category.as_str(), message);
+        self.measurement(&self.name, host, family)
+            .field("packet_loss", 1.0)
+            .failure(category, message)
+    }
+
+    fn measurement(&self, name: &str, host: &PingHost, family: Option<&str>) -> Measurement {
+        let measurement = Measurement::new(name).tag("host", &host.label);
+        match family {
+            Some(family) => measurement.tag("family", family),
+            None => measurement,
+        }
+    }
+
+    async fn ping(
+        &self,
+        host: &PingHost,
+        target: &Host,
+    ) -> Result<PingOutput, (ErrorCategory, String)> {
+        match self.config.method {
+            PingMethod::Command => {
+                run_ping(target, host.bytes, host.count, host.timeout, host.deadline).await
+            }
+            PingMethod::Native => {
+                run_native_ping(target, host.bytes, host.count, host.timeout, host.deadline).await
+            }
+        }
+    }
 }
 
 #[async_trait]
@@ -29,36 +135,30 @@ impl Service for PingService {
         format!("ping/{}", self.name)
     }
 
-    async fn execute(&self) -> anyhow::Result<()> {
-        #[cfg(target_os = "linux")]
-        debug!(target: &self.name, "Expecting Linux ping syntax");
-        #[cfg(target_os = "windows")]
-        debug!(target: &self.name, "Expecting Windows ping syntax");
-
-        //  Hosts are pinged in sequence to avoid interference
-        let mut data_points = Vec::new();
-        for host in &self.config.hosts {
-            debug!(target: &self.name, "Pinging host: {}", host);
-            let ping = run_ping(
-                &host,
-                self.config.bytes,
-                self.config.count,
-                self.config.timeout,
-            )
-            .await?;
-
-            let data_point = DataPoint::builder(&self.name)
-                .tag("host", host.to_string())
-                .field("packet_loss", ping.packet_loss)
-                .field("rtt_min", ping.rtt_min)
-                .field("rtt_avg", ping.rtt_avg)
-                .field("rtt_max", ping.rtt_max)
-                .build()?;
-            data_points.push(data_point);
+    async fn execute(&self) -> anyhow::Result<Vec<Measurement>> {
+        match self.config.method {
+            #[cfg(target_os = "linux")]
+            PingMethod::Command => debug!(target: &self.name, "Expecting Linux ping syntax"),
+            #[cfg(target_os = "windows")]
+            PingMethod::Command => debug!(target: &self.name, "Expecting Windows ping syntax"),
+            PingMethod::Native => debug!(target: &self.name, "Using native ICMP echo"),
         }
 
-        debug!(target: &self.name, "Writing data to DB");
-        self.db.writes(data_points).await?;
-        Ok(())
+        //  Hosts are pinged in sequence by default to avoid interference
+        let pings: Vec<_> = self
+            .config
+            .hosts
+            .iter()
+            .map(|host| self.measure(host))
+            .collect();
+        let measurements = stream::iter(pings)
+            .buffered(self.config.concurrency)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+
+        Ok(measurements)
     }
 }
@@ -1,7 +1,8 @@
+use crate::measurement::Measurement;
 use async_trait::async_trait;
 
 #[async_trait]
 pub trait Service {
     fn name(&self) -> String;
-    async fn execute(&self) -> anyhow::Result<()>;
+    async fn execute(&self) -> anyhow::Result<Vec<Measurement>>;
 }
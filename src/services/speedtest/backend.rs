@@ -0,0 +1,253 @@
+use crate::services::speedtest::config::SpeedtestBackend;
+use crate::services::speedtest::model::CliOutput;
+use serde::de::Error;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+/// Parses the output of `backend`, results of other backends are converted to the shape of the Ookla CLI.
+pub fn parse_output(backend: SpeedtestBackend, output: &str) -> serde_json::Result<CliOutput> {
+    match backend {
+        SpeedtestBackend::Ookla => parse_ookla_output(output),
+        SpeedtestBackend::Sivel => {
+            let output: SivelOutput = serde_json::from_str(output)?;
+            Ok(output.into())
+        }
+        SpeedtestBackend::Librespeed => {
+            let results: Vec<LibrespeedOutput> = serde_json::from_str(output)?;
+            let output = results
+                .into_iter()
+                .next()
+                .ok_or(serde_json::Error::custom("Missing result"))?;
+            Ok(output.into())
+        }
+    }
+}
+
+/// Ookla prints progress and log messages as separate JSON lines before the result,
+/// e.g. with `--progress=yes`, so the line of type `result` is picked.
+fn parse_ookla_output(output: &str) -> serde_json::Result<CliOutput> {
+    for line in output.lines() {
+        if let Ok(object) = serde_json::from_str::<Map<String, Value>>(line) {
+            if object.get("type").and_then(Value::as_str) == Some("result") {
+                return Ok(CliOutput::from(object));
+            }
+        }
+    }
+
+    //  Single document, e.g. pretty printed, or an error describing the invalid output
+    serde_json::from_str(output)
+}
+
+/// Output of `speedtest-cli --json`.
+#[derive(Debug, Deserialize)]
+struct SivelOutput {
+    /// Bits per second
+    download: f64,
+    /// Bits per second
+    upload: f64,
+    ping: f64,
+    timestamp: String,
+    bytes_sent: u64,
+    bytes_received: u64,
+    server: SivelServer,
+    client: SivelClient,
+}
+
+#[derive(Debug, Deserialize)]
+struct SivelServer {
+    id: String,
+    host: String,
+    /// Location of the server
+    name: String,
+    country: String,
+    /// Name of the server
+    sponsor: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SivelClient {
+    ip: String,
+    isp: String,
+}
+
+impl From<SivelOutput> for CliOutput {
+    fn from(output: SivelOutput) -> Self {
+        to_output(json!({
+            "type": "result",
+            "timestamp": output.timestamp,
+            "ping": {
+                "latency": output.ping,
+            },
+            "download": {
+                "bandwidth": output.download / 8.0,
+                "bytes": output.bytes_received,
+            },
+            "upload": {
+                "bandwidth": output.upload / 8.0,
+                "bytes": output.bytes_sent,
+            },
+            "isp": output.client.isp,
+            "interface": {
+                "external_ip": output.client.ip,
+            },
+            "server": {
+                "id": output.server.id.parse::<u64>().ok(),
+                "host": output.server.host,
+                "name": output.server.sponsor,
+                "location": output.server.name,
+                "country": output.server.country,
+            },
+        }))
+    }
+}
+
+/// Single result of `librespeed-cli --json`.
+#[derive(Debug, Deserialize)]
+struct LibrespeedOutput {
+    timestamp: String,
+    /// Megabits per second
+    download: f64,
+    /// Megabits per second
+    upload: f64,
+    ping: f64,
+    jitter: f64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    server: LibrespeedServer,
+    client: LibrespeedClient,
+}
+
+#[derive(Debug, Deserialize)]
+struct LibrespeedServer {
+    name: String,
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LibrespeedClient {
+    ip: String,
+    /// Name of the ISP, prefixed with its AS number
+    org: String,
+}
+
+impl From<LibrespeedOutput> for CliOutput {
+    fn from(output: LibrespeedOutput) -> Self {
+        to_output(json!({
+            "type": "result",
+            "timestamp": output.timestamp,
+            "ping": {
+                "latency": output.ping,
+                "jitter": output.jitter,
+            },
+            "download": {
+                "bandwidth": output.download * 1e6 / 8.0,
+                "bytes": output.bytes_received,
+            },
+            "upload": {
+                "bandwidth": output.upload * 1e6 / 8.0,
+                "bytes": output.bytes_sent,
+            },
+            "isp": output.client.org,
+            "interface": {
+                "external_ip": output.client.ip,
+            },
+            "server": {
+                "host": output.server.url,
+                "name": output.server.name,
+            },
+        }))
+    }
+}
+
+fn to_output(value: Value) -> CliOutput {
+    match value {
+        Value::Object(object) => CliOutput::from(object),
+        _ => CliOutput::from(Map::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::speedtest::model::AsInfluxDbData;
+    use chrono::{DateTime, Utc};
+    use influxdb2::models::FieldValue;
+
+    /// Output of `speedtest-cli --json` 2.1.3, the server `id` is a string.
+    const SIVEL_OUTPUT: &str = r#"{"download": 93460012.99487531, "upload": 23383779.09786516, "ping": 19.632, "server": {"url": "http://speedtest.example.net:8080/speedtest/upload.php", "lat": "50.0755", "lon": "14.4378", "name": "Prague", "country": "Czech Republic", "cc": "CZ", "sponsor": "Example ISP", "id": "21975", "host": "speedtest.example.net:8080", "d": 2.0143, "latency": 19.632}, "timestamp": "2024-01-20T10:00:00.123456Z", "bytes_sent": 30408704, "bytes_received": 117224960, "share": null, "client": {"ip": "203.0.113.10", "lat": "50.0880", "lon": "14.4208", "isp": "Example ISP", "isprating": "3.7", "rating": "0", "ispdlavg": "0", "ispulavg": "0", "loggedin": "0", "country": "CZ"}}"#;
+
+    /// Output of `librespeed-cli --json` 1.0.10, an array with one result per server.
+    const LIBRESPEED_OUTPUT: &str = r#"[{"timestamp":"2024-01-20T11:00:00.123456789+01:00","server":{"name":"Amsterdam, Netherlands (Clouvider)","url":"http://ams.speedtest.clouvider.net/backend"},"client":{"ip":"203.0.113.10","hostname":"","city":"Prague","region":"Prague","country":"CZ","loc":"50.0880,14.4208","org":"AS12345 Example ISP","postal":"110 00","timezone":"Europe/Prague"},"bytes_sent":25165824,"bytes_received":104857600,"ping":21.5,"jitter":1.25,"upload":20.13,"download":83.89,"share":""}]"#;
+
+    fn number(output: &CliOutput, name: &str) -> f64 {
+        match output.as_fields().get(name) {
+            Some(FieldValue::F64(value)) => *value,
+            Some(FieldValue::I64(value)) => *value as f64,
+            value => panic!("{name} is not a number: {value:?}"),
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "{actual} is not {expected}"
+        );
+    }
+
+    #[test]
+    fn sivel_output_is_normalized() {
+        let output = parse_output(SpeedtestBackend::Sivel, SIVEL_OUTPUT).unwrap();
+
+        assert_close(
+            number(&output, "download_bandwidth"),
+            93460012.99487531 / 8.0,
+        );
+        assert_close(number(&output, "upload_bandwidth"), 23383779.09786516 / 8.0);
+        assert_close(number(&output, "ping_latency"), 19.632);
+        assert_close(number(&output, "server_id"), 21975.0);
+        assert_close(number(&output, "total_bytes"), 147633664.0);
+        assert_eq!(output.as_tags()["server_name"], "Example ISP");
+        assert_eq!(output.as_tags()["server_location"], "Prague");
+        assert_eq!(output.as_tags()["timestamp"], "2024-01-20 10:00:00.123456");
+        let timestamp: DateTime<Utc> = "2024-01-20T10:00:00.123456Z".parse().unwrap();
+        assert_eq!(output.timestamp(), Some(timestamp));
+    }
+
+    #[test]
+    fn librespeed_output_is_normalized() {
+        let output = parse_output(SpeedtestBackend::Librespeed, LIBRESPEED_OUTPUT).unwrap();
+
+        assert_close(number(&output, "download_bandwidth"), 83.89e6 / 8.0);
+        assert_close(number(&output, "upload_bandwidth"), 20.13e6 / 8.0);
+        assert_close(number(&output, "ping_jitter"), 1.25);
+        assert_close(number(&output, "download_bytes"), 104857600.0);
+        assert_eq!(output.as_tags()["isp"], "AS12345 Example ISP");
+        assert_eq!(
+            output.as_tags()["server_host"],
+            "http://ams.speedtest.clouvider.net/backend"
+        );
+        //  Offset of the CLI is converted to UTC
+        let timestamp: DateTime<Utc> = "2024-01-20T10:00:00.123456789Z".parse().unwrap();
+        assert_eq!(output.timestamp(), Some(timestamp));
+    }
+
+    #[test]
+    fn librespeed_output_without_results_is_rejected() {
+        assert!(parse_output(SpeedtestBackend::Librespeed, "[]").is_err());
+    }
+
+    #[test]
+    fn ookla_result_is_picked_from_log_lines() {
+        let output = concat!(
+            r#"{"type":"log","timestamp":"2024-01-20T09:59:58Z","message":"Server selected","level":"info"}"#,
+            "\n",
+            r#"{"type":"result","timestamp":"2024-01-20T10:00:00Z","download":{"bandwidth":11682501,"bytes":117224960},"upload":{"bandwidth":2922972,"bytes":30408704}}"#,
+            "\n",
+        );
+        let output = parse_output(SpeedtestBackend::Ookla, output).unwrap();
+
+        assert_close(number(&output, "download_bandwidth"), 11682501.0);
+        assert_eq!(output.as_tags()["output_type"], "result");
+        assert_eq!(output.as_tags()["timestamp"], "2024-01-20 10:00:00");
+    }
+}
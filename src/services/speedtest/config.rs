@@ -1,6 +1,13 @@
-use clap::Args;
+use crate::config::{deserialize_comma_list, expand_instances, merge_instances, StringOrList};
+use crate::services::speedtest::selection::{parse_renames, parse_selectors, Selector};
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
 
-#[derive(Debug, Clone, Args)]
+#[derive(Debug, Clone, Default, Args, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct PartialSpeedtestConfig {
     #[arg(
         name = "SPEEDTEST_CRON",
@@ -19,26 +26,186 @@ pub struct PartialSpeedtestConfig {
         long = "speedtest-fields",
         env = "SPEEDTEST_FIELDS"
     )]
+    #[serde(default, deserialize_with = "deserialize_comma_list")]
     pub fields: Option<String>,
     #[arg(
         name = "SPEEDTEST_TAGS",
         long = "speedtest-tags",
         env = "SPEEDTEST_TAGS"
     )]
+    #[serde(default, deserialize_with = "deserialize_comma_list")]
     pub tags: Option<String>,
+    #[arg(
+        value_enum,
+        name = "SPEEDTEST_BANDWIDTH_UNIT",
+        long = "speedtest-bandwidth-unit",
+        env = "SPEEDTEST_BANDWIDTH_UNIT",
+        help = "Unit of `download_bandwidth` and `upload_bandwidth` [default: bytes]"
+    )]
+    pub bandwidth_unit: Option<BandwidthUnit>,
+    #[arg(
+        name = "SPEEDTEST_RENAME",
+        long = "speedtest-rename",
+        env = "SPEEDTEST_RENAME",
+        help = "Comma separated list of `name=new_name` of fields and tags"
+    )]
+    #[serde(default, deserialize_with = "deserialize_comma_list")]
+    pub rename: Option<String>,
+    #[arg(
+        name = "SPEEDTEST_MEASUREMENT",
+        long = "speedtest-measurement",
+        env = "SPEEDTEST_MEASUREMENT",
+        help = "[default: name of the instance]"
+    )]
+    pub measurement: Option<String>,
+    #[arg(
+        name = "SPEEDTEST_TIMEOUT",
+        long = "speedtest-timeout",
+        env = "SPEEDTEST_TIMEOUT",
+        help = "Time after which the speedtest is killed [seconds] [default: 300]"
+    )]
+    pub timeout: Option<u64>,
+    #[arg(
+        value_enum,
+        name = "SPEEDTEST_BACKEND",
+        long = "speedtest-backend",
+        env = "SPEEDTEST_BACKEND",
+        help = "[default: ookla]"
+    )]
+    pub backend: Option<SpeedtestBackend>,
+    #[arg(
+        name = "SPEEDTEST_BINARY",
+        long = "speedtest-binary",
+        env = "SPEEDTEST_BINARY",
+        help = "Path of the speedtest binary [default: depends on the backend]"
+    )]
+    pub binary: Option<PathBuf>,
+    #[arg(
+        name = "SPEEDTEST_ARGS",
+        long = "speedtest-args",
+        env = "SPEEDTEST_ARGS",
+        value_delimiter = ' ',
+        allow_hyphen_values = true,
+        help = "Space separated extra arguments of the speedtest binary"
+    )]
+    #[serde(default, deserialize_with = "deserialize_args")]
+    pub args: Option<Vec<String>>,
+    #[arg(
+        name = "SPEEDTEST_ENV",
+        long = "speedtest-env",
+        env = "SPEEDTEST_ENV",
+        help = "Comma separated list of `NAME=value` environment variables of the speedtest binary"
+    )]
+    #[serde(default, deserialize_with = "deserialize_comma_list")]
+    pub env: Option<String>,
+    #[arg(skip)]
+    #[serde(default)]
+    pub instances: BTreeMap<String, PartialSpeedtestConfig>,
+}
+
+impl PartialSpeedtestConfig {
+    /// Values of `self` take precedence over values of `other`.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            cron: self.cron.or(other.cron),
+            server: self.server.or(other.server),
+            fields: self.fields.or(other.fields),
+            tags: self.tags.or(other.tags),
+            bandwidth_unit: self.bandwidth_unit.or(other.bandwidth_unit),
+            rename: self.rename.or(other.rename),
+            measurement: self.measurement.or(other.measurement),
+            timeout: self.timeout.or(other.timeout),
+            backend: self.backend.or(other.backend),
+            binary: self.binary.or(other.binary),
+            args: self.args.or(other.args),
+            env: self.env.or(other.env),
+            instances: merge_instances(self.instances, other.instances),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct SpeedtestConfig {
     pub cron: String,
     pub server: Option<u64>,
-    pub fields: Vec<String>,
-    pub tags: Vec<String>,
+    pub fields: Vec<Selector>,
+    pub tags: Vec<Selector>,
+    pub bandwidth_unit: BandwidthUnit,
+    /// Output names of fields and tags
+    pub rename: BTreeMap<String, String>,
+    /// Overrides the name of the instance as the measurement
+    pub measurement: Option<String>,
+    pub timeout: Duration,
+    pub backend: SpeedtestBackend,
+    pub binary: PathBuf,
+    /// Passed after the default arguments
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedtestBackend {
+    /// Ookla `speedtest`, its license and GDPR statement are accepted automatically
+    Ookla,
+    /// `speedtest-cli` by sivel
+    Sivel,
+    /// `librespeed-cli`
+    Librespeed,
+}
+
+impl SpeedtestBackend {
+    pub fn default_binary(self) -> &'static str {
+        match self {
+            SpeedtestBackend::Ookla => "speedtest",
+            SpeedtestBackend::Sivel => "speedtest-cli",
+            SpeedtestBackend::Librespeed => "librespeed-cli",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BandwidthUnit {
+    /// Bytes per second, as reported by the speedtest CLI
+    Bytes,
+    /// Bits per second
+    Bits,
+    /// Kilobits per second
+    Kbps,
+    /// Megabits per second
+    Mbps,
+    /// Gigabits per second
+    Gbps,
+}
+
+impl BandwidthUnit {
+    /// Converts `bandwidth` in bytes per second to this unit.
+    pub fn convert(self, bandwidth: f64) -> f64 {
+        match self {
+            BandwidthUnit::Bytes => bandwidth,
+            BandwidthUnit::Bits => bandwidth * 8.0,
+            BandwidthUnit::Kbps => bandwidth * 8.0 / 1e3,
+            BandwidthUnit::Mbps => bandwidth * 8.0 / 1e6,
+            BandwidthUnit::Gbps => bandwidth * 8.0 / 1e9,
+        }
+    }
+}
+
+impl TryFrom<PartialSpeedtestConfig> for BTreeMap<String, SpeedtestConfig> {
+    type Error = String;
+    fn try_from(mut config: PartialSpeedtestConfig) -> Result<Self, Self::Error> {
+        let instances = std::mem::take(&mut config.instances);
+        expand_instances("speedtest", config, instances, "Speedtest")
+    }
 }
 
 impl TryFrom<PartialSpeedtestConfig> for Option<SpeedtestConfig> {
     type Error = String;
     fn try_from(config: PartialSpeedtestConfig) -> Result<Self, Self::Error> {
+        if !config.instances.is_empty() {
+            return Err("Instances can not be nested".to_string());
+        }
         if config.cron.is_none() && config.fields.is_none() && config.tags.is_none() {
             if config.server.is_some() {
                 return Err("SPEEDTEST_SERVER requires other \"SPEEDTEST_\" parameters".to_string());
@@ -53,21 +220,74 @@ impl TryFrom<PartialSpeedtestConfig> for Option<SpeedtestConfig> {
         let fields = config
             .fields
             .ok_or("SPEEDTEST_FIELDS is required for \"SPEEDTEST_\" parameters")
-            .map(|f| parse_comma_list(&f))??;
+            .map(|f| parse_selectors(&f))??;
         let tags = config
             .tags
             .ok_or("SPEEDTEST_TAGS is required for \"SPEEDTEST_\" parameters")
-            .map(|t| parse_comma_list(&t))??;
+            .map(|t| parse_selectors(&t))??;
+        let bandwidth_unit = config.bandwidth_unit.unwrap_or(BandwidthUnit::Bytes);
+        let rename = config
+            .rename
+            .map(|r| parse_renames(&r))
+            .transpose()?
+            .unwrap_or_default();
+        let measurement = config.measurement;
+        let timeout = config.timeout.unwrap_or(300);
+        if timeout == 0 {
+            return Err("SPEEDTEST_TIMEOUT must be greater than 0".to_string());
+        }
+        let backend = config.backend.unwrap_or(SpeedtestBackend::Ookla);
+        let binary = config
+            .binary
+            .unwrap_or(PathBuf::from(backend.default_binary()));
+        let args = config
+            .args
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|arg| !arg.is_empty())
+            .collect();
+        let env = config
+            .env
+            .map(|e| parse_env(&e))
+            .transpose()?
+            .unwrap_or_default();
 
         Ok(Some(SpeedtestConfig {
             cron,
             server,
             fields,
             tags,
+            bandwidth_unit,
+            rename,
+            measurement,
+            timeout: Duration::from_secs(timeout),
+            backend,
+            binary,
+            args,
+            env,
         }))
     }
 }
 
-fn parse_comma_list(text: &str) -> Result<Vec<String>, String> {
-    Ok(text.split(',').map(|s| s.to_string()).collect())
+fn parse_env(list: &str) -> Result<Vec<(String, String)>, String> {
+    list.split(',')
+        .filter(|item| !item.trim().is_empty())
+        .map(|item| {
+            let (name, value) = item.split_once('=').ok_or(format!(
+                "Invalid speedtest environment variable: {item}, expected NAME=value"
+            ))?;
+            Ok((name.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// Accepts both a space separated string and a list, which allows arguments with spaces.
+fn deserialize_args<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match StringOrList::deserialize(deserializer)? {
+        StringOrList::Text(text) => Ok(Some(text.split_whitespace().map(String::from).collect())),
+        StringOrList::List(list) => Ok(Some(list)),
+    }
 }
@@ -1,3 +1,5 @@
+mod backend;
 pub mod config;
 mod model;
+mod selection;
 pub mod service;
@@ -1,299 +1,250 @@
-use chrono::NaiveDateTime;
+use crate::measurement::ErrorCategory;
+use crate::services::speedtest::config::BandwidthUnit;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use influxdb2::models::FieldValue;
 use serde::Deserialize;
-use std::collections::HashMap;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Keys documented for the output of the speedtest CLI, used to validate the configuration.
+pub const KNOWN_KEYS: &[&str] = &[
+    "output_type",
+    "timestamp",
+    "ping_jitter",
+    "ping_latency",
+    "ping_low",
+    "ping_high",
+    "download_bandwidth",
+    "download_bytes",
+    "download_elapsed",
+    "download_latency_iqm",
+    "download_latency_low",
+    "download_latency_high",
+    "download_latency_jitter",
+    "upload_bandwidth",
+    "upload_bytes",
+    "upload_elapsed",
+    "upload_latency_iqm",
+    "upload_latency_low",
+    "upload_latency_high",
+    "upload_latency_jitter",
+    "packet_loss",
+    "isp",
+    "interface_internal_ip",
+    "interface_name",
+    "interface_mac_addr",
+    "interface_is_vpn",
+    "interface_external_ip",
+    "server_id",
+    "server_host",
+    "server_port",
+    "server_name",
+    "server_location",
+    "server_country",
+    "server_ip",
+    "result_id",
+    "result_url",
+    "result_persisted",
+    "download_bits_per_second",
+    "download_mbps",
+    "upload_bits_per_second",
+    "upload_mbps",
+    "total_bytes",
+];
+
+/// Keys written as floats even when the CLI prints a whole number, keeps field types stable in InfluxDB.
+const FLOAT_KEYS: &[&str] = &[
+    "packet_loss",
+    "ping_jitter",
+    "ping_latency",
+    "ping_low",
+    "ping_high",
+    "download_bandwidth",
+    "download_latency_iqm",
+    "download_latency_low",
+    "download_latency_high",
+    "download_latency_jitter",
+    "upload_bandwidth",
+    "upload_latency_iqm",
+    "upload_latency_low",
+    "upload_latency_high",
+    "upload_latency_jitter",
+    "download_bits_per_second",
+    "download_mbps",
+    "upload_bits_per_second",
+    "upload_mbps",
+];
+
+/// Format of the `timestamp` key, the same as `NaiveDateTime` is displayed in
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
 
 pub trait AsInfluxDbData {
-    fn as_fields(&self) -> HashMap<String, FieldValue>;
-    fn as_tags(&self) -> HashMap<String, String>;
+    fn as_fields(&self) -> BTreeMap<String, FieldValue>;
+    fn as_tags(&self) -> BTreeMap<String, String>;
 }
 
+/// Output of the Ookla speedtest CLI, or a result of another backend converted to it,
+/// flattened into snake_case keys joined with `_`, e.g. `download.latency.iqm` becomes `download_latency_iqm`.
+///
+/// The top level `type` is renamed to `output_type`, `null` values are left out.
+/// Bandwidth in bits per second, megabits per second and `total_bytes` of download and upload are added.
 #[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(from = "Map<String, Value>")]
 pub struct CliOutput {
-    #[serde(rename = "type")]
-    output_type: String,
-    #[serde(with = "speedtest_format")]
-    timestamp: NaiveDateTime,
-    ping: CliOutputPing,
-    download: CliOutputUpDown,
-    upload: CliOutputUpDown,
-    packet_loss: f64,
-    isp: String,
-    interface: CliOutputInterface,
-    server: CliOutputServer,
-    result: CliOutputResult,
-}
-
-impl AsInfluxDbData for CliOutput {
-    fn as_fields(&self) -> HashMap<String, FieldValue> {
-        let mut fields = HashMap::new();
-        fields.insert(
-            "output_type".to_string(),
-            FieldValue::String(self.output_type.clone()),
-        );
-        fields.insert(
-            "timestamp".to_string(),
-            FieldValue::String(self.timestamp.to_string()),
-        );
-        fields.insert("packet_loss".to_string(), FieldValue::F64(self.packet_loss));
-        fields.insert("isp".to_string(), FieldValue::String(self.isp.clone()));
-        for (name, value) in self.ping.as_fields() {
-            fields.insert(format!("ping_{name}"), value);
-        }
-        for (name, value) in self.download.as_fields() {
-            fields.insert(format!("download_{name}"), value);
-        }
-        for (name, value) in self.upload.as_fields() {
-            fields.insert(format!("upload_{name}"), value);
+    values: BTreeMap<String, Value>,
+}
+
+impl From<Map<String, Value>> for CliOutput {
+    fn from(object: Map<String, Value>) -> Self {
+        let mut values = BTreeMap::new();
+        for (key, value) in object {
+            let key = match key.as_str() {
+                "type" => "output_type".to_string(),
+                _ => to_snake_case(&key),
+            };
+            flatten(key, value, &mut values);
         }
-        for (name, value) in self.interface.as_fields() {
-            fields.insert(format!("interface_{name}"), value);
-        }
-        for (name, value) in self.server.as_fields() {
-            fields.insert(format!("server_{name}"), value);
-        }
-        for (name, value) in self.result.as_fields() {
-            fields.insert(format!("result_{name}"), value);
-        }
-        fields
-    }
 
-    fn as_tags(&self) -> HashMap<String, String> {
-        let mut tags = HashMap::new();
-        tags.insert("packet_loss".to_string(), self.packet_loss.to_string());
-        tags.insert("isp".to_string(), self.isp.clone());
-        for (name, value) in self.ping.as_tags() {
-            tags.insert(format!("ping_{name}"), value);
-        }
-        for (name, value) in self.download.as_tags() {
-            tags.insert(format!("download_{name}"), value);
-        }
-        for (name, value) in self.upload.as_tags() {
-            tags.insert(format!("upload_{name}"), value);
-        }
-        for (name, value) in self.interface.as_tags() {
-            tags.insert(format!("interface_{name}"), value);
-        }
-        for (name, value) in self.server.as_tags() {
-            tags.insert(format!("server_{name}"), value);
+        for direction in ["download", "upload"] {
+            if let Some(bandwidth) = values
+                .get(&format!("{direction}_bandwidth"))
+                .and_then(Value::as_f64)
+            {
+                let bits = bandwidth * 8.0;
+                values.insert(format!("{direction}_bits_per_second"), Value::from(bits));
+                values.insert(format!("{direction}_mbps"), Value::from(bits / 1e6));
+            }
         }
-        for (name, value) in self.result.as_tags() {
-            tags.insert(format!("result_{name}"), value);
-        }
-        tags
-    }
-}
 
-#[derive(Debug, Deserialize)]
-pub struct CliOutputPing {
-    jitter: f64,
-    latency: f64,
-    low: f64,
-    high: f64,
-}
-
-impl AsInfluxDbData for CliOutputPing {
-    fn as_fields(&self) -> HashMap<String, FieldValue> {
-        let mut fields = HashMap::new();
-        fields.insert("jitter".to_string(), FieldValue::F64(self.jitter));
-        fields.insert("latency".to_string(), FieldValue::F64(self.latency));
-        fields.insert("low".to_string(), FieldValue::F64(self.low));
-        fields.insert("high".to_string(), FieldValue::F64(self.high));
-        fields
-    }
-
-    fn as_tags(&self) -> HashMap<String, String> {
-        let mut tags = HashMap::new();
-        tags.insert("jitter".to_string(), self.jitter.to_string());
-        tags.insert("latency".to_string(), self.latency.to_string());
-        tags.insert("low".to_string(), self.low.to_string());
-        tags.insert("high".to_string(), self.high.to_string());
-        tags
-    }
-}
-
-#[derive(Debug, Deserialize)]
-pub struct CliOutputUpDown {
-    bandwidth: f64,
-    bytes: u64,
-    elapsed: u64,
-    latency: CliOutputUpDownLatency,
-}
-
-impl AsInfluxDbData for CliOutputUpDown {
-    fn as_fields(&self) -> HashMap<String, FieldValue> {
-        let mut fields = HashMap::new();
-        fields.insert("bandwidth".to_string(), FieldValue::F64(self.bandwidth));
-        fields.insert("bytes".to_string(), FieldValue::I64(self.bytes as i64));
-        fields.insert("elapsed".to_string(), FieldValue::I64(self.elapsed as i64));
-        for (name, value) in self.latency.as_fields() {
-            fields.insert(format!("latency_{name}"), value);
+        //  Rendered as before the output was flattened, e.g. `2024-01-01 12:00:00`, series keyed on it stay the same
+        if let Some(Value::String(timestamp)) = values.get_mut("timestamp") {
+            if let Ok(parsed) = DateTime::parse_from_rfc3339(timestamp) {
+                *timestamp = parsed.naive_utc().to_string();
+            }
         }
-        fields
-    }
 
-    fn as_tags(&self) -> HashMap<String, String> {
-        let mut tags = HashMap::new();
-        tags.insert("bandwidth".to_string(), self.bandwidth.to_string());
-        tags.insert("bytes".to_string(), self.bytes.to_string());
-        tags.insert("elapsed".to_string(), self.elapsed.to_string());
-        for (name, value) in self.latency.as_tags() {
-            tags.insert(format!("latency_{name}"), value);
+        let download_bytes = values.get("download_bytes").and_then(Value::as_u64);
+        let upload_bytes = values.get("upload_bytes").and_then(Value::as_u64);
+        if let (Some(download_bytes), Some(upload_bytes)) = (download_bytes, upload_bytes) {
+            values.insert(
+                "total_bytes".to_string(),
+                Value::from(download_bytes + upload_bytes),
+            );
         }
-        tags
-    }
-}
 
-#[derive(Debug, Deserialize)]
-pub struct CliOutputUpDownLatency {
-    iqm: f64,
-    low: f64,
-    high: f64,
-    jitter: f64,
-}
-
-impl AsInfluxDbData for CliOutputUpDownLatency {
-    fn as_fields(&self) -> HashMap<String, FieldValue> {
-        let mut fields = HashMap::new();
-        fields.insert("iqm".to_string(), FieldValue::F64(self.iqm));
-        fields.insert("low".to_string(), FieldValue::F64(self.low));
-        fields.insert("high".to_string(), FieldValue::F64(self.high));
-        fields.insert("jitter".to_string(), FieldValue::F64(self.jitter));
-        fields
-    }
-
-    fn as_tags(&self) -> HashMap<String, String> {
-        let mut tags = HashMap::new();
-        tags.insert("iqm".to_string(), self.iqm.to_string());
-        tags.insert("low".to_string(), self.low.to_string());
-        tags.insert("high".to_string(), self.high.to_string());
-        tags.insert("jitter".to_string(), self.jitter.to_string());
-        tags
+        Self { values }
     }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct CliOutputInterface {
-    internal_ip: String,
-    name: String,
-    mac_addr: String,
-    is_vpn: bool,
-    external_ip: String,
-}
-
-impl AsInfluxDbData for CliOutputInterface {
-    fn as_fields(&self) -> HashMap<String, FieldValue> {
-        let mut fields = HashMap::new();
-        fields.insert(
-            "internal_ip".to_string(),
-            FieldValue::String(self.internal_ip.clone()),
-        );
-        fields.insert("name".to_string(), FieldValue::String(self.name.clone()));
-        fields.insert(
-            "mac_addr".to_string(),
-            FieldValue::String(self.mac_addr.clone()),
-        );
-        fields.insert("is_vpn".to_string(), FieldValue::Bool(self.is_vpn));
-        fields.insert(
-            "external_ip".to_string(),
-            FieldValue::String(self.external_ip.clone()),
-        );
-        fields
+impl CliOutput {
+    /// Time of the test reported by the CLI.
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        let timestamp = self.values.get("timestamp")?.as_str()?;
+        let timestamp = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+        Some(timestamp.and_utc())
     }
 
-    fn as_tags(&self) -> HashMap<String, String> {
-        let mut tags = HashMap::new();
-        tags.insert("internal_ip".to_string(), self.internal_ip.clone());
-        tags.insert("name".to_string(), self.name.clone());
-        tags.insert("mac_addr".to_string(), self.mac_addr.clone());
-        tags.insert("is_vpn".to_string(), self.is_vpn.to_string());
-        tags.insert("external_ip".to_string(), self.external_ip.clone());
-        tags
+    /// Converts `download_bandwidth` and `upload_bandwidth` from bytes per second.
+    pub fn convert_bandwidth(&mut self, unit: BandwidthUnit) {
+        for key in ["download_bandwidth", "upload_bandwidth"] {
+            if let Some(value) = self.values.get_mut(key) {
+                if let Some(bandwidth) = value.as_f64() {
+                    *value = Value::from(unit.convert(bandwidth));
+                }
+            }
+        }
     }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct CliOutputServer {
-    id: u64,
-    host: String,
-    port: u16,
-    name: String,
-    location: String,
-    country: String,
-    ip: String,
-}
-
-impl AsInfluxDbData for CliOutputServer {
-    fn as_fields(&self) -> HashMap<String, FieldValue> {
-        let mut fields = HashMap::new();
-        fields.insert("id".to_string(), FieldValue::I64(self.id as i64));
-        fields.insert("host".to_string(), FieldValue::String(self.host.clone()));
-        fields.insert("port".to_string(), FieldValue::I64(self.port as i64));
-        fields.insert("name".to_string(), FieldValue::String(self.name.clone()));
-        fields.insert(
-            "location".to_string(),
-            FieldValue::String(self.location.clone()),
-        );
-        fields.insert(
-            "country".to_string(),
-            FieldValue::String(self.country.clone()),
-        );
-        fields.insert("ip".to_string(), FieldValue::String(self.ip.clone()));
+impl AsInfluxDbData for CliOutput {
+    fn as_fields(&self) -> BTreeMap<String, FieldValue> {
+        let mut fields = BTreeMap::new();
+        for (name, value) in &self.values {
+            let value = match value {
+                Value::Number(number) if FLOAT_KEYS.contains(&name.as_str()) => {
+                    number.as_f64().map(FieldValue::F64)
+                }
+                Value::Number(number) => number
+                    .as_i64()
+                    .map(FieldValue::I64)
+                    .or(number.as_f64().map(FieldValue::F64)),
+                Value::Bool(value) => Some(FieldValue::Bool(*value)),
+                Value::String(value) => Some(FieldValue::String(value.clone())),
+                _ => None,
+            };
+            if let Some(value) = value {
+                fields.insert(name.clone(), value);
+            }
+        }
         fields
     }
 
-    fn as_tags(&self) -> HashMap<String, String> {
-        let mut tags = HashMap::new();
-        tags.insert("id".to_string(), self.id.to_string());
-        tags.insert("host".to_string(), self.host.clone());
-        tags.insert("port".to_string(), self.port.to_string());
-        tags.insert("name".to_string(), self.name.clone());
-        tags.insert("location".to_string(), self.location.clone());
-        tags.insert("country".to_string(), self.country.clone());
-        tags.insert("ip".to_string(), self.ip.clone());
+    fn as_tags(&self) -> BTreeMap<String, String> {
+        let mut tags = BTreeMap::new();
+        for (name, value) in &self.values {
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            tags.insert(name.clone(), value);
+        }
         tags
     }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct CliOutputResult {
-    id: String,
-    url: String,
-    persisted: bool,
-}
-
-impl AsInfluxDbData for CliOutputResult {
-    fn as_fields(&self) -> HashMap<String, FieldValue> {
-        let mut fields = HashMap::new();
-        fields.insert("id".to_string(), FieldValue::String(self.id.clone()));
-        fields.insert("url".to_string(), FieldValue::String(self.url.clone()));
-        fields.insert("persisted".to_string(), FieldValue::Bool(self.persisted));
-        fields
+/// Guesses the reason of a failed run from messages of the CLI.
+pub fn error_category(output: &str) -> ErrorCategory {
+    let output = output.to_lowercase();
+    if output.contains("license") || output.contains("gdpr") {
+        ErrorCategory::License
+    } else if [
+        "no servers",
+        "no matched servers",
+        "cannot find server",
+        "server not found",
+    ]
+    .iter()
+    .any(|message| output.contains(message))
+    {
+        ErrorCategory::NoServer
+    } else if output.contains("timeout") || output.contains("timed out") {
+        ErrorCategory::Timeout
+    } else {
+        ErrorCategory::Unknown
     }
+}
 
-    fn as_tags(&self) -> HashMap<String, String> {
-        let mut tags = HashMap::new();
-        tags.insert("id".to_string(), self.id.clone());
-        tags.insert("url".to_string(), self.url.clone());
-        tags.insert("persisted".to_string(), self.persisted.to_string());
-        tags
+/// Objects are joined by their keys and arrays by their indexes.
+fn flatten(key: String, value: Value, values: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(object) => {
+            for (name, value) in object {
+                flatten(format!("{key}_{}", to_snake_case(&name)), value, values);
+            }
+        }
+        Value::Array(array) => {
+            for (index, value) in array.into_iter().enumerate() {
+                flatten(format!("{key}_{index}"), value, values);
+            }
+        }
+        Value::Null => {}
+        value => {
+            values.insert(key, value);
+        }
     }
 }
 
-mod speedtest_format {
-    use chrono::NaiveDateTime;
-    use serde::{self, Deserialize, Deserializer};
-
-    const FORMAT: &'static str = "%Y-%m-%dT%H:%M:%SZ";
-
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let s = String::deserialize(deserializer)?;
-        let dt = NaiveDateTime::parse_from_str(&s, FORMAT).map_err(serde::de::Error::custom)?;
-        Ok(dt)
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_uppercase() {
+            if !snake.is_empty() {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
     }
+    snake
 }
@@ -0,0 +1,176 @@
+use crate::services::speedtest::model::KNOWN_KEYS;
+use std::collections::BTreeMap;
+
+/// Prefix of names that are not checked against the known keys, e.g. keys added by a newer CLI
+const RAW_PREFIX: &str = "raw:";
+
+const PRESETS: &[(&str, &[&str])] = &[
+    (
+        "minimal",
+        &["download_bandwidth", "upload_bandwidth", "ping_latency"],
+    ),
+    (
+        "bandwidth",
+        &[
+            "download_bandwidth",
+            "download_bytes",
+            "download_elapsed",
+            "upload_bandwidth",
+            "upload_bytes",
+            "upload_elapsed",
+        ],
+    ),
+    ("all", &["*"]),
+];
+
+/// Selects fields or tags from the speedtest output.
+#[derive(Debug, Clone)]
+pub enum Selector {
+    /// Exact name, e.g. `download_bandwidth`
+    Name(String),
+    /// Name with `*` wildcards, e.g. `download_*`, matched against every key of the output
+    Pattern(String),
+}
+
+impl Selector {
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            Selector::Name(selected) => selected == name,
+            Selector::Pattern(pattern) => wildcard_match(pattern, name),
+        }
+    }
+}
+
+/// Parses comma separated names, patterns and presets.
+///
+/// Names and patterns have to match at least one of the known keys, so typos are found at startup,
+/// unless they are prefixed with `raw:`.
+pub fn parse_selectors(list: &str) -> Result<Vec<Selector>, String> {
+    let mut selectors = Vec::new();
+    for item in list
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        if let Some((_, names)) = PRESETS.iter().find(|(preset, _)| *preset == item) {
+            selectors.extend(names.iter().map(|name| parse_selector(name)));
+            continue;
+        }
+
+        if let Some(name) = item.strip_prefix(RAW_PREFIX) {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(format!("Empty speedtest field or tag: {item}"));
+            }
+            selectors.push(Selector::Name(name.to_string()));
+            continue;
+        }
+
+        let selector = parse_selector(item);
+        if !KNOWN_KEYS.iter().any(|key| selector.matches(key)) {
+            return Err(format!("Unknown speedtest field or tag: {item}"));
+        }
+        selectors.push(selector);
+    }
+    Ok(selectors)
+}
+
+/// Parses comma separated `name=new_name` pairs, names have to be one of the known keys,
+/// unless they are prefixed with `raw:`.
+pub fn parse_renames(list: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut renames = BTreeMap::new();
+    for item in list
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        let (name, new_name) = item.split_once('=').ok_or(format!(
+            "Invalid speedtest rename: {item}, expected name=new_name"
+        ))?;
+        let (name, new_name) = (name.trim(), new_name.trim());
+        let name = match name.strip_prefix(RAW_PREFIX) {
+            Some(raw) if raw.trim().is_empty() => {
+                return Err(format!("Empty speedtest field or tag: {name}"))
+            }
+            Some(raw) => raw.trim(),
+            None if KNOWN_KEYS.contains(&name) => name,
+            None => return Err(format!("Unknown speedtest field or tag: {name}")),
+        };
+        if new_name.is_empty() {
+            return Err(format!("Empty new name of speedtest field or tag: {name}"));
+        }
+        renames.insert(name.to_string(), new_name.to_string());
+    }
+    Ok(renames)
+}
+
+fn parse_selector(item: &str) -> Selector {
+    if item.contains('*') {
+        Selector::Pattern(item.to_string())
+    } else {
+        Selector::Name(item.to_string())
+    }
+}
+
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => match name.strip_prefix(prefix) {
+            Some(name) => (0..=name.len())
+                .filter(|i| name.is_char_boundary(*i))
+                .any(|i| wildcard_match(rest, &name[i..])),
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_match_any_part_of_the_name() {
+        assert!(wildcard_match("download_*", "download_bandwidth"));
+        assert!(wildcard_match("*_bandwidth", "upload_bandwidth"));
+        assert!(wildcard_match("*latency*", "download_latency_iqm"));
+        assert!(wildcard_match("download_*_iqm", "download_latency_iqm"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("ping_*", "ping_"));
+        assert!(!wildcard_match("download_*", "upload_bandwidth"));
+        assert!(!wildcard_match("*_iqm", "download_latency_iqm_x"));
+        assert!(!wildcard_match("ping", "ping_latency"));
+    }
+
+    #[test]
+    fn renames_are_parsed() {
+        let renames = parse_renames(" download_bandwidth = down, ping_latency=ping ,").unwrap();
+
+        assert_eq!(renames.len(), 2);
+        assert_eq!(renames["download_bandwidth"], "down");
+        assert_eq!(renames["ping_latency"], "ping");
+    }
+
+    #[test]
+    fn raw_renames_skip_the_known_keys() {
+        let renames = parse_renames("raw:new_key=renamed").unwrap();
+
+        assert_eq!(renames["new_key"], "renamed");
+    }
+
+    #[test]
+    fn invalid_renames_are_rejected() {
+        assert!(parse_renames("download_bandwith=down").is_err());
+        assert!(parse_renames("download_bandwidth").is_err());
+        assert!(parse_renames("download_bandwidth=").is_err());
+        assert!(parse_renames("raw:=renamed").is_err());
+    }
+
+    #[test]
+    fn selectors_are_checked_against_the_known_keys() {
+        assert!(parse_selectors("download_*, ping_latency, minimal").is_ok());
+        assert!(parse_selectors("raw:new_key").is_ok());
+        assert!(parse_selectors("download_bandwith").is_err());
+        assert!(parse_selectors("nothing_*").is_err());
+        assert!(parse_selectors("raw:").is_err());
+    }
+}
@@ -1,98 +1,166 @@
-use crate::influxdb::InfluxDB;
+use crate::measurement::{ErrorCategory, Measurement};
 use crate::services::service::Service;
-use crate::services::speedtest::config::SpeedtestConfig;
-use crate::services::speedtest::model::{AsInfluxDbData, CliOutput};
+use crate::services::speedtest::backend::parse_output;
+use crate::services::speedtest::config::{SpeedtestBackend, SpeedtestConfig};
+use crate::services::speedtest::model::{error_category, AsInfluxDbData, CliOutput};
+use crate::services::speedtest::selection::Selector;
 use async_trait::async_trait;
-use influxdb2::models::data_point::DataPointError;
-use influxdb2::models::DataPoint;
+use chrono::{DateTime, Utc};
 use log::{debug, warn};
-use std::sync::Arc;
 use tokio::process::Command;
+use tokio::time::timeout;
 
 pub struct SpeedtestService {
-    db: Arc<InfluxDB>,
     config: SpeedtestConfig,
     name: String,
 }
 
 impl SpeedtestService {
-    pub fn new(db: Arc<InfluxDB>, config: SpeedtestConfig, name: &str) -> Self {
+    pub fn new(config: SpeedtestConfig, name: &str) -> Self {
         Self {
-            db,
             config,
             name: name.to_string(),
         }
     }
 
-    fn build_data_point(&self, data: &CliOutput) -> Result<DataPoint, DataPointError> {
+    /// The point is stamped with the time reported by the CLI, or `started` when it is missing.
+    fn build_measurement(&self, data: &CliOutput, started: DateTime<Utc>) -> Measurement {
         let as_fields = data.as_fields();
         let as_tags = data.as_tags();
 
-        let mut builder = DataPoint::builder(&self.name);
-        for field in &self.config.fields {
-            if let Some(value) = as_fields.get(field) {
-                builder = builder.field(field, value.clone());
-            } else {
-                warn!(target: &self.name, "Unknown field: {}", field);
+        for selector in &self.config.fields {
+            if let Selector::Name(name) = selector {
+                if !as_fields.contains_key(name) {
+                    warn!(target: &self.name, "Missing field: {}", name);
+                }
             }
         }
 
-        for tag in &self.config.tags {
-            if let Some(value) = as_tags.get(tag) {
-                builder = builder.tag(tag, value.clone());
-            } else {
-                warn!(target: &self.name, "Unknown tag: {}", tag);
+        for selector in &self.config.tags {
+            if let Selector::Name(name) = selector {
+                if !as_tags.contains_key(name) {
+                    warn!(target: &self.name, "Missing tag: {}", name);
+                }
             }
         }
 
-        builder.build()
+        let measurement_name = self.config.measurement.as_ref().unwrap_or(&self.name);
+        let mut measurement = Measurement::new(measurement_name)
+            .timestamp(data.timestamp().unwrap_or(started))
+            .field("success", 1i64);
+        for (name, value) in as_fields {
+            if self.config.fields.iter().any(|s| s.matches(&name)) {
+                measurement = measurement.field(self.output_name(name), value);
+            }
+        }
+
+        for (name, value) in as_tags {
+            if self.config.tags.iter().any(|s| s.matches(&name)) {
+                measurement = measurement.tag(self.output_name(name), value);
+            }
+        }
+
+        measurement
     }
-}
 
-#[async_trait]
-impl Service for SpeedtestService {
-    fn name(&self) -> String {
-        format!("speedtest/{}", self.name)
+    /// Failed run is written as a point tagged with `status` and `error_category`, with `success=0`.
+    fn failure(
+        &self,
+        category: ErrorCategory,
+        message: String,
+        started: DateTime<Utc>,
+    ) -> Measurement {
+        let measurement_name = self.config.measurement.as_ref().unwrap_or(&self.name);
+        Measurement::new(measurement_name)
+            .timestamp(started)
+            .failure(category, message)
     }
 
-    async fn execute(&self) -> anyhow::Result<()> {
+    async fn run(&self) -> Result<CliOutput, (ErrorCategory, String)> {
         debug!(target: &self.name, "Executing command");
-        let mut cmd = Command::new("speedtest");
-        cmd.arg("--accept-license")
-            .arg("--accept-gdpr")
-            .arg("--format=json");
+        let mut cmd = Command::new(&self.config.binary);
+        let server_arg = match self.config.backend {
+            SpeedtestBackend::Ookla => {
+                cmd.arg("--accept-license")
+                    .arg("--accept-gdpr")
+                    .arg("--format=json");
+                "--server-id"
+            }
+            SpeedtestBackend::Sivel | SpeedtestBackend::Librespeed => {
+                cmd.arg("--json");
+                "--server"
+            }
+        };
 
         if let Some(server) = &self.config.server {
             debug!(target: &self.name, "Using server: {}", server);
-            cmd.arg("--server-id").arg(server.to_string());
+            cmd.arg(server_arg).arg(server.to_string());
         }
 
-        let output = cmd.output().await?;
+        if !self.config.args.is_empty() {
+            debug!(target: &self.name, "Using extra arguments: {:?}", self.config.args);
+            cmd.args(&self.config.args);
+        }
+        cmd.envs(self.config.env.iter().map(|(name, value)| (name, value)));
+
+        //  Child is killed when the timeout drops the output future
+        cmd.kill_on_drop(true);
+        let output = timeout(self.config.timeout, cmd.output())
+            .await
+            .map_err(|_| {
+                let message = format!("Killed after {:?}", self.config.timeout);
+                (ErrorCategory::Timeout, message)
+            })?
+            .map_err(|e| {
+                (
+                    ErrorCategory::Command,
+                    format!("Failed to run command: {e}"),
+                )
+            })?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
         if !output.status.success() {
-            if let Ok(stderr) = String::from_utf8(output.stderr) {
-                warn!(target: &self.name, "Command failed: {}, stderr: {}", output.status, stderr);
-            } else {
-                warn!(target: &self.name, "Command failed: {}", output.status);
-            }
+            warn!(target: &self.name, "Command failed: {}, stdout: {}, stderr: {}", output.status, stdout, stderr);
+            let category = error_category(&format!("{stdout}\n{stderr}"));
+            let message = match stderr.trim() {
+                "" => format!("Command failed: {}", output.status),
+                stderr => format!("Command failed: {}, {}", output.status, stderr),
+            };
+            return Err((category, message));
         }
 
-        let output = String::from_utf8(output.stdout)?;
-
         debug!(target: &self.name, "Parsing output");
-        let data: Result<CliOutput, _> = serde_json::from_str(&output);
-        if let Err(err) = &data {
-            warn!(target: &self.name, "Failed to parse output: {}, stdout: {}", err, output);
-        }
+        parse_output(self.config.backend, &stdout).map_err(|e| {
+            warn!(target: &self.name, "Failed to parse output: {}, stdout: {}", e, stdout);
+            (ErrorCategory::Parse, format!("Failed to parse output: {e}"))
+        })
+    }
 
-        let data = data?;
+    fn output_name(&self, name: String) -> String {
+        self.config.rename.get(&name).cloned().unwrap_or(name)
+    }
+}
 
-        debug!(target: &self.name, "Building data point");
-        let data_point = self.build_data_point(&data)?;
-        let data_points = vec![data_point];
+#[async_trait]
+impl Service for SpeedtestService {
+    fn name(&self) -> String {
+        format!("speedtest/{}", self.name)
+    }
+
+    async fn execute(&self) -> anyhow::Result<Vec<Measurement>> {
+        let started = Utc::now();
+        let mut data = match self.run().await {
+            Ok(data) => data,
+            Err((category, message)) => {
+                warn!(target: &self.name, "Speedtest failed ({}): {}", category.as_str(), message);
+                return Ok(vec![self.failure(category, message, started)]);
+            }
+        };
+        data.convert_bandwidth(self.config.bandwidth_unit);
 
-        debug!(target: &self.name, "Writing data to DB");
-        self.db.writes(data_points).await?;
+        debug!(target: &self.name, "Building measurement");
+        let measurement = self.build_measurement(&data, started);
 
-        Ok(())
+        Ok(vec![measurement])
     }
 }
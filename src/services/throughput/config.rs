@@ -0,0 +1,126 @@
+use crate::config::{deserialize_http_url, expand_instances, merge_instances, parse_http_url};
+use clap::Args;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use url::Url;
+
+#[derive(Debug, Clone, Default, Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartialThroughputConfig {
+    #[arg(
+        name = "THROUGHPUT_CRON",
+        long = "throughput-cron",
+        env = "THROUGHPUT_CRON"
+    )]
+    pub cron: Option<String>,
+    #[arg(
+        name = "THROUGHPUT_DOWNLOAD_URL",
+        long = "throughput-download-url",
+        env = "THROUGHPUT_DOWNLOAD_URL",
+        value_parser = parse_http_url
+    )]
+    #[serde(default, deserialize_with = "deserialize_http_url")]
+    pub download_url: Option<Url>,
+    #[arg(
+        name = "THROUGHPUT_UPLOAD_URL",
+        long = "throughput-upload-url",
+        env = "THROUGHPUT_UPLOAD_URL",
+        value_parser = parse_http_url
+    )]
+    #[serde(default, deserialize_with = "deserialize_http_url")]
+    pub upload_url: Option<Url>,
+    #[arg(
+        name = "THROUGHPUT_CONNECTIONS",
+        long = "throughput-connections",
+        env = "THROUGHPUT_CONNECTIONS",
+        help = "Number of parallel connections [default: 4]"
+    )]
+    pub connections: Option<usize>,
+    #[arg(
+        name = "THROUGHPUT_DURATION",
+        long = "throughput-duration",
+        env = "THROUGHPUT_DURATION",
+        help = "Time limit of every direction [seconds] [default: 10]"
+    )]
+    pub duration: Option<u64>,
+    #[arg(
+        name = "THROUGHPUT_SIZE",
+        long = "throughput-size",
+        env = "THROUGHPUT_SIZE",
+        help = "Data limit of every direction [bytes]"
+    )]
+    pub size: Option<u64>,
+    #[arg(skip)]
+    #[serde(default)]
+    pub instances: BTreeMap<String, PartialThroughputConfig>,
+}
+
+impl PartialThroughputConfig {
+    /// Values of `self` take precedence over values of `other`.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            cron: self.cron.or(other.cron),
+            download_url: self.download_url.or(other.download_url),
+            upload_url: self.upload_url.or(other.upload_url),
+            connections: self.connections.or(other.connections),
+            duration: self.duration.or(other.duration),
+            size: self.size.or(other.size),
+            instances: merge_instances(self.instances, other.instances),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ThroughputConfig {
+    pub cron: String,
+    pub download_url: Url,
+    pub upload_url: Option<Url>,
+    pub connections: usize,
+    pub duration: Duration,
+    pub size: Option<u64>,
+}
+
+impl TryFrom<PartialThroughputConfig> for BTreeMap<String, ThroughputConfig> {
+    type Error = String;
+    fn try_from(mut config: PartialThroughputConfig) -> Result<Self, Self::Error> {
+        let instances = std::mem::take(&mut config.instances);
+        expand_instances("throughput", config, instances, "Throughput")
+    }
+}
+
+impl TryFrom<PartialThroughputConfig> for Option<ThroughputConfig> {
+    type Error = String;
+    fn try_from(config: PartialThroughputConfig) -> Result<Self, Self::Error> {
+        if !config.instances.is_empty() {
+            return Err("Instances can not be nested".to_string());
+        }
+        if config.cron.is_none() && config.download_url.is_none() && config.upload_url.is_none() {
+            return Ok(None);
+        }
+
+        let cron = config
+            .cron
+            .ok_or("THROUGHPUT_CRON is required for \"THROUGHPUT_\" parameters")?;
+        let download_url = config
+            .download_url
+            .ok_or("THROUGHPUT_DOWNLOAD_URL is required for \"THROUGHPUT_\" parameters")?;
+        let connections = config.connections.unwrap_or(4);
+        if connections == 0 {
+            return Err("THROUGHPUT_CONNECTIONS must be greater than 0".to_string());
+        }
+        let duration = config.duration.unwrap_or(10);
+        if duration == 0 {
+            return Err("THROUGHPUT_DURATION must be greater than 0".to_string());
+        }
+
+        Ok(Some(ThroughputConfig {
+            cron,
+            download_url,
+            upload_url: config.upload_url,
+            connections,
+            duration: Duration::from_secs(duration),
+            size: config.size,
+        }))
+    }
+}
@@ -0,0 +1,289 @@
+use crate::measurement::{ErrorCategory, Measurement};
+use crate::services::service::Service;
+use crate::services::throughput::config::ThroughputConfig;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::future::try_join_all;
+use log::{debug, warn};
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::{Body, Client};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::time::error::Elapsed;
+use tokio::time::{timeout_at, Instant};
+use url::Url;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time given to the server to respond after the upload ends
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+static UPLOAD_CHUNK: [u8; CHUNK_SIZE] = [0; CHUNK_SIZE];
+
+struct Transfer {
+    bytes: u64,
+    elapsed: Duration,
+    /// Time until the first byte of any response body, only downloads
+    ttfb: Option<Duration>,
+}
+
+/// What a single download connection got until the limits were reached.
+#[derive(Default)]
+struct Received {
+    /// Headers of at least one response arrived
+    responded: bool,
+    /// Time until the first byte of any response body
+    ttfb: Option<Duration>,
+}
+
+pub struct ThroughputService {
+    config: ThroughputConfig,
+    name: String,
+    client: Client,
+}
+
+impl ThroughputService {
+    pub fn new(config: ThroughputConfig, name: &str) -> anyhow::Result<Self> {
+        let client = Client::builder().connect_timeout(CONNECT_TIMEOUT).build()?;
+        Ok(Self {
+            config,
+            name: name.to_string(),
+            client,
+        })
+    }
+
+    fn measurement(
+        &self,
+        direction: &str,
+        url: &Url,
+        started: DateTime<Utc>,
+        transfer: anyhow::Result<Transfer>,
+    ) -> Measurement {
+        let measurement = Measurement::new(&self.name)
+            .tag("direction", direction)
+            .tag("url", url.as_str())
+            .timestamp(started);
+
+        match transfer {
+            Ok(transfer) => {
+                let seconds = transfer.elapsed.as_secs_f64();
+                let measurement = measurement
+                    .field("success", 1i64)
+                    .field("bits_per_second", transfer.bytes as f64 * 8.0 / seconds)
+                    .field("bytes", transfer.bytes as i64)
+                    .field("duration", seconds);
+                match transfer.ttfb {
+                    Some(ttfb) => measurement.field("ttfb", ttfb.as_secs_f64() * 1000.0),
+                    None => measurement,
+                }
+            }
+            Err(e) => {
+                let category = error_category(&e);
+                warn!(target: &self.name, "Failed to measure {} of {} ({}): {}", direction, url, category.as_str(), e);
+                measurement.failure(category, e.to_string())
+            }
+        }
+    }
+
+    async fn download(&self, url: &Url) -> anyhow::Result<Transfer> {
+        let started = Instant::now();
+        let deadline = started + self.config.duration;
+        let total = AtomicU64::new(0);
+        let connections = (0..self.config.connections)
+            .map(|_| self.download_connection(url, started, deadline, &total));
+        let received = try_join_all(connections).await?;
+
+        let bytes = total.into_inner();
+        if !received.iter().any(|received| received.responded) {
+            let message = format!("No response from {url} within {:?}", self.config.duration);
+            return Err(timed_out(message));
+        }
+        if bytes == 0 {
+            let message = format!("No data from {url} within {:?}", self.config.duration);
+            return Err(timed_out(message));
+        }
+
+        Ok(Transfer {
+            bytes,
+            elapsed: started.elapsed(),
+            ttfb: received.iter().filter_map(|received| received.ttfb).min(),
+        })
+    }
+
+    /// Downloads the URL repeatedly until the deadline or the size limit.
+    async fn download_connection(
+        &self,
+        url: &Url,
+        started: Instant,
+        deadline: Instant,
+        total: &AtomicU64,
+    ) -> anyhow::Result<Received> {
+        let mut received = Received::default();
+        while !self.is_done(deadline, total.load(Ordering::Relaxed)) {
+            let Ok(response) = timeout_at(deadline, self.client.get(url.clone()).send()).await
+            else {
+                break;
+            };
+            let mut response = response?.error_for_status()?;
+            received.responded = true;
+
+            let mut length = 0;
+            loop {
+                let Ok(chunk) = timeout_at(deadline, response.chunk()).await else {
+                    return Ok(received);
+                };
+                let Some(chunk) = chunk? else {
+                    break;
+                };
+                received.ttfb.get_or_insert(started.elapsed());
+                length += chunk.len();
+                let size = chunk.len() as u64;
+                if self.is_done(deadline, total.fetch_add(size, Ordering::Relaxed) + size) {
+                    return Ok(received);
+                }
+            }
+
+            if length == 0 {
+                return Err(anyhow::anyhow!("Response of {} is empty", url));
+            }
+        }
+        Ok(received)
+    }
+
+    async fn upload(&self, url: &Url) -> anyhow::Result<Transfer> {
+        let started = Instant::now();
+        let deadline = started + self.config.duration;
+        let total = Arc::new(AtomicU64::new(0));
+        let count = self.config.connections as u64;
+        let connections = (0..count).map(|connection| {
+            //  Data limit is split between the connections, their bodies have a known length
+            let length = self
+                .config
+                .size
+                .map(|size| size / count + u64::from(connection < size % count));
+            let ended = Arc::new(OnceLock::new());
+            let body = self.upload_body(deadline, length, total.clone(), ended.clone());
+            let mut request = self.client.post(url.clone()).body(body);
+            if let Some(length) = length {
+                request = request.header(CONTENT_LENGTH, length);
+            }
+            let request = request.send();
+            //  Chunks handed to the connection only count once the server confirms the upload
+            async move {
+                let response = timeout_at(deadline + RESPONSE_TIMEOUT, request)
+                    .await
+                    .map_err(|e| {
+                        let message = match ended.get() {
+                            Some(_) => format!("No response from {url} after the upload"),
+                            None => format!("Upload to {url} did not finish in time"),
+                        };
+                        anyhow::Error::new(e).context(message)
+                    })?;
+                response?.error_for_status()?;
+                anyhow::Ok(ended.get().copied().unwrap_or_else(Instant::now))
+            }
+        });
+        let ended = try_join_all(connections).await?.into_iter().max();
+
+        let bytes = total.load(Ordering::Relaxed);
+        if bytes == 0 {
+            let message = format!("No data sent to {url} within {:?}", self.config.duration);
+            return Err(timed_out(message));
+        }
+
+        //  Waiting for the response of the server is not part of the upload
+        Ok(Transfer {
+            bytes,
+            elapsed: ended.unwrap_or_else(Instant::now) - started,
+            ttfb: None,
+        })
+    }
+
+    /// Chunks are counted when they are handed to the connection, `ended` is set when the last one is.
+    ///
+    /// Without a known `length` the body ends at the deadline, with one the upload fails at the deadline.
+    fn upload_body(
+        &self,
+        deadline: Instant,
+        length: Option<u64>,
+        total: Arc<AtomicU64>,
+        ended: Arc<OnceLock<Instant>>,
+    ) -> Body {
+        let chunks = futures::stream::unfold(0, move |sent| {
+            let total = total.clone();
+            let ended = ended.clone();
+            async move {
+                let chunk = match length {
+                    Some(length) if sent < length && Instant::now() >= deadline => {
+                        let error = io::Error::new(io::ErrorKind::TimedOut, "Time limit reached");
+                        return Some((Err(error), sent));
+                    }
+                    Some(length) => (length - sent).min(CHUNK_SIZE as u64) as usize,
+                    None if Instant::now() >= deadline => 0,
+                    None => CHUNK_SIZE,
+                };
+                if chunk == 0 {
+                    let _ = ended.set(Instant::now());
+                    return None;
+                }
+
+                let sent = sent + chunk as u64;
+                total.fetch_add(chunk as u64, Ordering::Relaxed);
+                if length == Some(sent) {
+                    let _ = ended.set(Instant::now());
+                }
+                Some((Ok::<_, io::Error>(&UPLOAD_CHUNK[..chunk]), sent))
+            }
+        });
+        Body::wrap_stream(chunks)
+    }
+
+    fn is_done(&self, deadline: Instant, transferred: u64) -> bool {
+        Instant::now() >= deadline || self.config.size.is_some_and(|size| transferred >= size)
+    }
+}
+
+/// Transfer that got nothing from the server before the time limit.
+fn timed_out(message: String) -> anyhow::Error {
+    io::Error::new(io::ErrorKind::TimedOut, message).into()
+}
+
+/// Time limits and errors of reqwest are told apart, anything else is unknown.
+fn error_category(error: &anyhow::Error) -> ErrorCategory {
+    let timed_out = |e: &io::Error| e.kind() == io::ErrorKind::TimedOut;
+    if error.downcast_ref::<Elapsed>().is_some() || error.downcast_ref().is_some_and(timed_out) {
+        return ErrorCategory::Timeout;
+    }
+    match error.downcast_ref::<reqwest::Error>() {
+        Some(e) if e.is_connect() => ErrorCategory::Connect,
+        Some(e) if e.is_timeout() => ErrorCategory::Timeout,
+        Some(e) if e.is_status() => ErrorCategory::Status,
+        _ => ErrorCategory::Unknown,
+    }
+}
+
+#[async_trait]
+impl Service for ThroughputService {
+    fn name(&self) -> String {
+        format!("throughput/{}", self.name)
+    }
+
+    async fn execute(&self) -> anyhow::Result<Vec<Measurement>> {
+        let url = &self.config.download_url;
+        debug!(target: &self.name, "Measuring download from {}", url);
+        let started = Utc::now();
+        let download = self.download(url).await;
+        let mut measurements = vec![self.measurement("download", url, started, download)];
+
+        if let Some(url) = &self.config.upload_url {
+            debug!(target: &self.name, "Measuring upload to {}", url);
+            let started = Utc::now();
+            let upload = self.upload(url).await;
+            measurements.push(self.measurement("upload", url, started, upload));
+        }
+
+        Ok(measurements)
+    }
+}
@@ -0,0 +1,172 @@
+use chrono::Utc;
+use influxdb2::models::{DataPoint, WriteDataPoint};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::io;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::fs;
+use tokio::sync::Mutex;
+
+const REPLAY_BATCH_SIZE: usize = 500;
+
+/// Data point in line protocol waiting to be written, stamped with the time it was measured.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BufferedPoint {
+    timestamp: i64,
+    line: String,
+}
+
+impl BufferedPoint {
+    pub fn new(data_point: &DataPoint, timestamp: i64) -> io::Result<Self> {
+        let mut line = Vec::new();
+        data_point.write_data_point_to(&mut line)?;
+        let line =
+            String::from_utf8(line).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+        Ok(Self {
+            timestamp,
+            line: line.trim_end().to_string(),
+        })
+    }
+}
+
+impl WriteDataPoint for BufferedPoint {
+    fn write_data_point_to<W>(&self, mut w: W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        w.write_all(self.line.as_bytes())?;
+        w.write_all(b"\n")
+    }
+}
+
+/// File backed queue of data points that failed to be written.
+///
+/// Every entry is one JSON line, the oldest entries are dropped when the
+/// buffer exceeds `max_size` points or they are older than `max_age`.
+pub struct WriteBuffer {
+    path: PathBuf,
+    max_size: usize,
+    max_age: Duration,
+    lock: Mutex<()>,
+}
+
+impl WriteBuffer {
+    pub fn new(path: PathBuf, max_size: usize, max_age: Duration) -> Self {
+        Self {
+            path,
+            max_size,
+            max_age,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub async fn pending(&self) -> anyhow::Result<usize> {
+        let _guard = self.lock.lock().await;
+        Ok(self.load().await?.len())
+    }
+
+    pub async fn push(&self, points: Vec<BufferedPoint>) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut buffered = self.load().await?;
+        buffered.extend(points);
+        self.store(&buffered).await
+    }
+
+    /// Passes buffered points in order and in batches to `write`, stops on first failure.
+    ///
+    /// Returns number of points that were replayed.
+    pub async fn replay<F, Fut>(&self, write: F) -> anyhow::Result<usize>
+    where
+        F: Fn(Vec<BufferedPoint>) -> Fut,
+        Fut: Future<Output = anyhow::Result<()>>,
+    {
+        let _guard = self.lock.lock().await;
+        let buffered = self.load().await?;
+
+        let mut replayed = 0;
+        for batch in buffered.chunks(REPLAY_BATCH_SIZE) {
+            if let Err(e) = write(batch.to_vec()).await {
+                self.store(&buffered[replayed..]).await?;
+                return Err(e);
+            }
+            replayed += batch.len();
+        }
+
+        self.store(&[]).await?;
+        Ok(replayed)
+    }
+
+    /// Lines that are not valid points are dropped and the file is rewritten without them,
+    /// so a single corrupted line does not block the whole buffer.
+    async fn load(&self) -> anyhow::Result<Vec<BufferedPoint>> {
+        let content = match fs::read(&self.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let oldest = Utc::now().timestamp_nanos_opt().unwrap_or(i64::MAX)
+            - self.max_age.as_nanos().min(i64::MAX as u128) as i64;
+        let mut invalid = false;
+        let points: Vec<BufferedPoint> = String::from_utf8_lossy(&content)
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(point) => Some(point),
+                Err(e) => {
+                    warn!("Dropping invalid line of {}: {}", self.path.display(), e);
+                    invalid = true;
+                    None
+                }
+            })
+            .collect();
+        if invalid {
+            self.store(&points).await?;
+        }
+
+        let total = points.len();
+        let points: Vec<_> = points
+            .into_iter()
+            .filter(|point| point.timestamp >= oldest)
+            .collect();
+        if points.len() < total {
+            warn!(
+                "Dropping {} buffered points older than {:?}",
+                total - points.len(),
+                self.max_age
+            );
+        }
+        Ok(points)
+    }
+
+    async fn store(&self, points: &[BufferedPoint]) -> anyhow::Result<()> {
+        let skip = points.len().saturating_sub(self.max_size);
+        if skip > 0 {
+            warn!(
+                "Dropping {} oldest buffered points over the limit of {}",
+                skip, self.max_size
+            );
+        }
+        if points.len() == skip {
+            return match fs::remove_file(&self.path).await {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+
+        let mut content = String::new();
+        for point in &points[skip..] {
+            content.push_str(&serde_json::to_string(point)?);
+            content.push('\n');
+        }
+
+        //  Written to a temporary file first so a crash never leaves a truncated buffer
+        let temporary = self.path.with_extension("tmp");
+        fs::write(&temporary, content).await?;
+        fs::rename(&temporary, &self.path).await?;
+        Ok(())
+    }
+}
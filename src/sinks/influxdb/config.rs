@@ -0,0 +1,139 @@
+use crate::config::{deserialize_http_url, parse_http_url};
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::time::Duration;
+use url::Url;
+
+#[derive(Debug, Clone, Default, Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartialInfluxDBConfig {
+    #[arg(
+        name = "INFLUXDB_URL",
+        long = "influxdb-url",
+        env = "INFLUXDB_URL",
+        value_parser = parse_http_url
+    )]
+    #[serde(default, deserialize_with = "deserialize_http_url")]
+    pub url: Option<Url>,
+    #[arg(
+        name = "INFLUXDB_TOKEN",
+        long = "influxdb-token",
+        env = "INFLUXDB_TOKEN"
+    )]
+    pub token: Option<String>,
+    #[arg(
+        name = "INFLUXDB_ORG",
+        long = "influxdb-org",
+        env = "INFLUXDB_ORG",
+        help = "[default: org]"
+    )]
+    pub org: Option<String>,
+    #[arg(
+        name = "INFLUXDB_BUCKET",
+        long = "influxdb-bucket",
+        env = "INFLUXDB_BUCKET",
+        help = "[default: speedtest]"
+    )]
+    pub bucket: Option<String>,
+    #[arg(
+        name = "INFLUXDB_BUFFER_PATH",
+        long = "influxdb-buffer-path",
+        env = "INFLUXDB_BUFFER_PATH"
+    )]
+    pub buffer_path: Option<PathBuf>,
+    #[arg(
+        name = "INFLUXDB_BUFFER_MAX_SIZE",
+        long = "influxdb-buffer-max-size",
+        env = "INFLUXDB_BUFFER_MAX_SIZE",
+        help = "[points] [default: 10000]"
+    )]
+    pub buffer_max_size: Option<usize>,
+    #[arg(
+        name = "INFLUXDB_BUFFER_MAX_AGE",
+        long = "influxdb-buffer-max-age",
+        env = "INFLUXDB_BUFFER_MAX_AGE",
+        help = "[seconds] [default: 604800]"
+    )]
+    pub buffer_max_age: Option<u64>,
+    #[arg(
+        value_enum,
+        name = "INFLUXDB_TIMESTAMP",
+        long = "influxdb-timestamp",
+        env = "INFLUXDB_TIMESTAMP",
+        help = "[default: measured]"
+    )]
+    pub timestamp: Option<TimestampSource>,
+}
+
+impl PartialInfluxDBConfig {
+    /// Values of `self` take precedence over values of `other`.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            url: self.url.or(other.url),
+            token: self.token.or(other.token),
+            org: self.org.or(other.org),
+            bucket: self.bucket.or(other.bucket),
+            buffer_path: self.buffer_path.or(other.buffer_path),
+            buffer_max_size: self.buffer_max_size.or(other.buffer_max_size),
+            buffer_max_age: self.buffer_max_age.or(other.buffer_max_age),
+            timestamp: self.timestamp.or(other.timestamp),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InfluxDBConfig {
+    pub url: Url,
+    pub token: String,
+    pub org: String,
+    pub bucket: String,
+    pub buffer: Option<BufferConfig>,
+    pub timestamp: TimestampSource,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampSource {
+    /// Time the measurement was taken at, e.g. reported by the speedtest CLI
+    Measured,
+    /// Time the point is written at, buffered points keep the time of the first attempt
+    Write,
+}
+
+#[derive(Debug, Clone)]
+pub struct BufferConfig {
+    pub path: PathBuf,
+    pub max_size: usize,
+    pub max_age: Duration,
+}
+
+impl TryFrom<PartialInfluxDBConfig> for Option<InfluxDBConfig> {
+    type Error = String;
+    fn try_from(config: PartialInfluxDBConfig) -> Result<Self, Self::Error> {
+        if config.url.is_none() && config.token.is_none() {
+            return Ok(None);
+        }
+
+        let url = config
+            .url
+            .ok_or("INFLUXDB_URL is required for \"INFLUXDB_\" parameters")?;
+        let token = config
+            .token
+            .ok_or("INFLUXDB_TOKEN is required for \"INFLUXDB_\" parameters")?;
+        let buffer = config.buffer_path.map(|path| BufferConfig {
+            path,
+            max_size: config.buffer_max_size.unwrap_or(10000),
+            max_age: Duration::from_secs(config.buffer_max_age.unwrap_or(604800)),
+        });
+
+        Ok(Some(InfluxDBConfig {
+            url,
+            token,
+            org: config.org.unwrap_or("org".to_string()),
+            bucket: config.bucket.unwrap_or("speedtest".to_string()),
+            buffer,
+            timestamp: config.timestamp.unwrap_or(TimestampSource::Measured),
+        }))
+    }
+}
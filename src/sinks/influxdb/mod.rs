@@ -0,0 +1,3 @@
+mod buffer;
+pub mod config;
+pub mod sink;
@@ -0,0 +1,222 @@
+use crate::measurement::Measurement;
+use crate::sinks::influxdb::buffer::{BufferedPoint, WriteBuffer};
+use crate::sinks::influxdb::config::{BufferConfig, TimestampSource};
+use crate::sinks::sink::Sink;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use influxdb2::models::data_point::DataPointError;
+use influxdb2::models::{DataPoint, HealthCheck, Status, WriteDataPoint};
+use influxdb2::{Client, RequestError};
+use log::{debug, error, info, warn};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+
+const REPLAY_MIN_BACKOFF: Duration = Duration::from_secs(5);
+const REPLAY_MAX_BACKOFF: Duration = Duration::from_secs(300);
+/// Limits how long a hung connection can hold the buffer
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct InfluxDB {
+    client: Client,
+    bucket: String,
+    buffer: Option<Arc<WriteBuffer>>,
+    timestamp: TimestampSource,
+}
+
+impl InfluxDB {
+    pub fn new(url: &str, org: &str, token: &str, bucket: &str) -> Self {
+        let client = Client::new(url, org, token);
+        Self {
+            client,
+            bucket: bucket.to_string(),
+            buffer: None,
+            timestamp: TimestampSource::Measured,
+        }
+    }
+
+    pub fn with_timestamp(mut self, timestamp: TimestampSource) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Failed writes are stored in the buffer and replayed in the background.
+    pub fn with_buffer(mut self, config: &BufferConfig) -> Self {
+        let buffer = WriteBuffer::new(config.path.clone(), config.max_size, config.max_age);
+        let buffer = Arc::new(buffer);
+        tokio::spawn(replay(
+            self.client.clone(),
+            self.bucket.clone(),
+            buffer.clone(),
+        ));
+        self.buffer = Some(buffer);
+        self
+    }
+
+    pub async fn check_health(&self) -> anyhow::Result<HealthCheck> {
+        Ok(self.client.health().await?)
+    }
+
+    /// Points without a timestamp get the receive time of the server,
+    /// buffered points are always stamped, so replayed points keep their time.
+    fn timestamp(&self, measurement: &Measurement, now: DateTime<Utc>) -> Option<i64> {
+        let timestamp = match self.timestamp {
+            TimestampSource::Measured => measurement.timestamp,
+            TimestampSource::Write => None,
+        };
+        let timestamp = match &self.buffer {
+            Some(_) => Some(timestamp.unwrap_or(now)),
+            None => timestamp,
+        };
+        timestamp.map(|t| t.timestamp_nanos_opt().unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl Sink for InfluxDB {
+    fn name(&self) -> String {
+        format!("influxdb/{}", self.bucket)
+    }
+
+    async fn write(&self, measurements: &[Measurement]) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let timestamps = measurements
+            .iter()
+            .map(|m| self.timestamp(m, now))
+            .collect::<Vec<_>>();
+        let data_points = measurements
+            .iter()
+            .zip(&timestamps)
+            .map(|(m, timestamp)| build_data_point(m, *timestamp))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let Some(buffer) = &self.buffer else {
+            return write_points(&self.client, &self.bucket, data_points).await;
+        };
+
+        let buffered = data_points
+            .iter()
+            .zip(&timestamps)
+            .map(|(p, timestamp)| BufferedPoint::new(p, timestamp.unwrap_or_default()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        //  Older points are still waiting, new ones are queued behind them to keep the order
+        if buffer.pending().await? > 0 {
+            debug!("Buffering {} points behind pending writes", buffered.len());
+            return buffer.push(buffered).await;
+        }
+
+        let result = write_points(&self.client, &self.bucket, data_points).await;
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) if is_retryable(&e) => {
+                warn!(
+                    "Failed to write to InfluxDB, buffering {} points: {}",
+                    buffered.len(),
+                    e
+                );
+                buffer.push(buffered).await
+            }
+            //  Rejected points would be rejected again, buffering them would block later points
+            Err(e) => Err(anyhow::anyhow!(
+                "InfluxDB rejected {} points, dropping them: {}",
+                buffered.len(),
+                e
+            )),
+        }
+    }
+}
+
+async fn write_points<P>(client: &Client, bucket: &str, points: Vec<P>) -> anyhow::Result<()>
+where
+    P: WriteDataPoint + Send + Sync + 'static,
+{
+    timeout(
+        WRITE_TIMEOUT,
+        client.write(bucket, tokio_stream::iter(points)),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("Write timed out after {:?}", WRITE_TIMEOUT))??;
+    Ok(())
+}
+
+/// Connection failures, timeouts, server errors and rate limiting are worth retrying later.
+fn is_retryable(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<RequestError>() {
+        Some(RequestError::Http { status, .. }) => {
+            status.is_server_error() || status.as_u16() == 429
+        }
+        Some(RequestError::ReqwestProcessing { .. }) => true,
+        Some(_) => false,
+        None => true,
+    }
+}
+
+async fn replay(client: Client, bucket: String, buffer: Arc<WriteBuffer>) {
+    let mut backoff = REPLAY_MIN_BACKOFF;
+    loop {
+        sleep(backoff).await;
+        match replay_buffer(&client, &bucket, &buffer).await {
+            Ok(0) => backoff = REPLAY_MIN_BACKOFF,
+            Ok(replayed) => {
+                info!("Replayed {} buffered points to InfluxDB", replayed);
+                backoff = REPLAY_MIN_BACKOFF;
+            }
+            Err(e) => {
+                backoff = (backoff * 2).min(REPLAY_MAX_BACKOFF);
+                warn!(
+                    "Failed to replay buffered points, retrying in {:?}: {}",
+                    backoff, e
+                );
+            }
+        }
+    }
+}
+
+async fn replay_buffer(
+    client: &Client,
+    bucket: &str,
+    buffer: &WriteBuffer,
+) -> anyhow::Result<usize> {
+    if buffer.pending().await? == 0 {
+        return Ok(0);
+    }
+
+    let health = client.health().await?;
+    if health.status != Status::Pass {
+        return Err(anyhow::anyhow!("InfluxDB is not healthy"));
+    }
+
+    buffer
+        .replay(|points| async move {
+            let count = points.len();
+            match write_points(client, bucket, points).await {
+                Err(e) if !is_retryable(&e) => {
+                    error!(
+                        "InfluxDB rejected {} buffered points, dropping them: {}",
+                        count, e
+                    );
+                    Ok(())
+                }
+                result => result,
+            }
+        })
+        .await
+}
+
+fn build_data_point(
+    measurement: &Measurement,
+    timestamp: Option<i64>,
+) -> Result<DataPoint, DataPointError> {
+    let mut builder = DataPoint::builder(&measurement.name);
+    for (name, value) in &measurement.tags {
+        builder = builder.tag(name, value);
+    }
+    for (name, value) in &measurement.fields {
+        builder = builder.field(name, value.clone());
+    }
+    if let Some(timestamp) = timestamp {
+        builder = builder.timestamp(timestamp);
+    }
+    builder.build()
+}
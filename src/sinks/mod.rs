@@ -0,0 +1,3 @@
+pub mod influxdb;
+pub mod prometheus;
+pub mod sink;
@@ -0,0 +1,52 @@
+use clap::Args;
+use serde::Deserialize;
+use std::net::SocketAddr;
+
+#[derive(Debug, Clone, Default, Args, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PartialPrometheusConfig {
+    #[arg(
+        name = "PROMETHEUS_LISTEN",
+        long = "prometheus-listen",
+        env = "PROMETHEUS_LISTEN",
+        help = "[address:port]"
+    )]
+    pub listen: Option<SocketAddr>,
+    #[arg(
+        name = "PROMETHEUS_PREFIX",
+        long = "prometheus-prefix",
+        env = "PROMETHEUS_PREFIX",
+        help = "[default: speedflux]"
+    )]
+    pub prefix: Option<String>,
+}
+
+impl PartialPrometheusConfig {
+    /// Values of `self` take precedence over values of `other`.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            listen: self.listen.or(other.listen),
+            prefix: self.prefix.or(other.prefix),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PrometheusConfig {
+    pub listen: SocketAddr,
+    pub prefix: String,
+}
+
+impl TryFrom<PartialPrometheusConfig> for Option<PrometheusConfig> {
+    type Error = String;
+    fn try_from(config: PartialPrometheusConfig) -> Result<Self, Self::Error> {
+        let Some(listen) = config.listen else {
+            return Ok(None);
+        };
+
+        Ok(Some(PrometheusConfig {
+            listen,
+            prefix: config.prefix.unwrap_or("speedflux".to_string()),
+        }))
+    }
+}
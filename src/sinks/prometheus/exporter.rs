@@ -0,0 +1,137 @@
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{debug, error};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+
+pub type Labels = BTreeMap<String, String>;
+pub type Series = BTreeMap<Labels, BTreeMap<String, f64>>;
+
+static NO_LABELS: Labels = BTreeMap::new();
+
+/// Latest fields of every measurement, keyed by measurement name and labels.
+#[derive(Debug, Default)]
+pub struct Gauges {
+    measurements: BTreeMap<String, Series>,
+    /// Time of the last point without an error of every measurement, in seconds since the epoch
+    last_success: BTreeMap<String, f64>,
+}
+
+impl Gauges {
+    /// Replaces all series of the measurement, so values missing from `series` are no longer exported.
+    pub fn replace(&mut self, measurement: &str, series: Series) {
+        let series = series
+            .into_iter()
+            .map(|(labels, fields)| {
+                let labels = labels
+                    .into_iter()
+                    .map(|(k, v)| (sanitize_name(&k), v))
+                    .collect();
+                (labels, fields)
+            })
+            .collect();
+        self.measurements.insert(measurement.to_string(), series);
+    }
+
+    pub fn set_last_success(&mut self, measurement: &str, timestamp: f64) {
+        self.last_success.insert(measurement.to_string(), timestamp);
+    }
+
+    /// Renders the gauges in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut metrics: BTreeMap<String, Vec<(&Labels, f64)>> = BTreeMap::new();
+        for (measurement, series) in &self.measurements {
+            for (labels, fields) in series {
+                for (field, value) in fields {
+                    let name = sanitize_name(&format!("{measurement}_{field}"));
+                    metrics.entry(name).or_default().push((labels, *value));
+                }
+            }
+        }
+        for (measurement, timestamp) in &self.last_success {
+            let name = sanitize_name(&format!("{measurement}_last_success_timestamp_seconds"));
+            metrics
+                .entry(name)
+                .or_default()
+                .push((&NO_LABELS, *timestamp));
+        }
+
+        let mut output = String::new();
+        for (name, series) in &metrics {
+            let _ = writeln!(output, "# TYPE {name} gauge");
+            for (labels, value) in series {
+                let labels = labels
+                    .iter()
+                    .map(|(k, v)| format!("{k}=\"{}\"", escape_label_value(v)))
+                    .collect::<Vec<_>>();
+                if labels.is_empty() {
+                    let _ = writeln!(output, "{name} {value}");
+                } else {
+                    let _ = writeln!(output, "{name}{{{}}} {value}", labels.join(","));
+                }
+            }
+        }
+        output
+    }
+}
+
+pub fn serve(listen: SocketAddr, gauges: Arc<RwLock<Gauges>>) -> anyhow::Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let gauges = gauges.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle(request, gauges.clone()))) }
+    });
+
+    let server = Server::try_bind(&listen)?.serve(make_service);
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            error!("Prometheus exporter failed: {}", e);
+        }
+    });
+    Ok(())
+}
+
+async fn handle(
+    request: Request<Body>,
+    gauges: Arc<RwLock<Gauges>>,
+) -> Result<Response<Body>, Infallible> {
+    debug!(
+        "Prometheus exporter request: {} {}",
+        request.method(),
+        request.uri()
+    );
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => {
+            let body = gauges.read().map(|g| g.render()).unwrap_or_default();
+            Response::builder()
+                .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                .body(Body::from(body))
+        }
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+
+    Ok(response.unwrap_or_default())
+}
+
+fn sanitize_name(name: &str) -> String {
+    let mut name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
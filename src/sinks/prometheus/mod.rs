@@ -0,0 +1,3 @@
+pub mod config;
+mod exporter;
+pub mod sink;
@@ -0,0 +1,80 @@
+use crate::measurement::Measurement;
+use crate::sinks::prometheus::config::PrometheusConfig;
+use crate::sinks::prometheus::exporter::{serve, Gauges, Series};
+use crate::sinks::sink::Sink;
+use async_trait::async_trait;
+use chrono::Utc;
+use influxdb2::models::FieldValue;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+/// Keeps the latest numeric fields of every measurement and exposes them as gauges.
+///
+/// Every write replaces all series of the measurements it contains, so values of
+/// a failed run or of a host that stopped responding are not exported any more.
+pub struct Prometheus {
+    config: PrometheusConfig,
+    gauges: Arc<RwLock<Gauges>>,
+}
+
+impl Prometheus {
+    pub fn new(config: PrometheusConfig) -> Self {
+        Self {
+            config,
+            gauges: Arc::new(RwLock::new(Gauges::default())),
+        }
+    }
+
+    pub fn start(&self) -> anyhow::Result<()> {
+        serve(self.config.listen, self.gauges.clone())
+    }
+}
+
+#[async_trait]
+impl Sink for Prometheus {
+    fn name(&self) -> String {
+        format!("prometheus/{}", self.config.listen)
+    }
+
+    async fn write(&self, measurements: &[Measurement]) -> anyhow::Result<()> {
+        let mut gauges = self
+            .gauges
+            .write()
+            .map_err(|_| anyhow::anyhow!("Gauges lock is poisoned"))?;
+
+        let now = Utc::now();
+        let mut measurement_series: BTreeMap<&str, Series> = BTreeMap::new();
+        let mut last_success = BTreeMap::new();
+        for measurement in measurements {
+            let fields = measurement_series
+                .entry(&measurement.name)
+                .or_default()
+                .entry(measurement.tags.clone())
+                .or_default();
+            for (field, value) in &measurement.fields {
+                let value = match value {
+                    FieldValue::Bool(value) => f64::from(u8::from(*value)),
+                    FieldValue::F64(value) => *value,
+                    FieldValue::I64(value) => *value as f64,
+                    FieldValue::String(_) => continue,
+                };
+                fields.insert(field.clone(), value);
+            }
+
+            if !measurement.fields.contains_key("error") {
+                let timestamp = measurement.timestamp.unwrap_or(now).timestamp_millis();
+                let latest = last_success.entry(&measurement.name).or_insert(timestamp);
+                *latest = (*latest).max(timestamp);
+            }
+        }
+
+        for (name, series) in measurement_series {
+            gauges.replace(&format!("{}_{}", self.config.prefix, name), series);
+        }
+        for (name, timestamp) in last_success {
+            let name = format!("{}_{}", self.config.prefix, name);
+            gauges.set_last_success(&name, timestamp as f64 / 1000.0);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use crate::measurement::Measurement;
+use async_trait::async_trait;
+use log::error;
+use std::sync::Arc;
+
+#[async_trait]
+pub trait Sink: Send + Sync {
+    fn name(&self) -> String;
+    async fn write(&self, measurements: &[Measurement]) -> anyhow::Result<()>;
+}
+
+/// Set of configured sinks, every measurement is written to all of them.
+#[derive(Clone, Default)]
+pub struct Sinks {
+    sinks: Vec<Arc<dyn Sink>>,
+}
+
+impl Sinks {
+    pub fn add<S>(&mut self, sink: S)
+    where
+        S: Sink + 'static,
+    {
+        self.sinks.push(Arc::new(sink));
+    }
+
+    /// Writes to every sink even if some of them fail, the error lists all failed sinks.
+    pub async fn write(&self, measurements: &[Measurement]) -> anyhow::Result<()> {
+        let mut failed = Vec::new();
+        for sink in &self.sinks {
+            if let Err(e) = sink.write(measurements).await {
+                error!("Sink \"{}\" failed to write: {}", sink.name(), e);
+                failed.push(sink.name());
+            }
+        }
+
+        if !failed.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Failed to write to sinks: {}",
+                failed.join(", ")
+            ));
+        }
+        Ok(())
+    }
+}